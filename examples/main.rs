@@ -44,4 +44,10 @@ fn main() {
     let crop_xy = None; //Some([100, 100]);
     let crop_wh = None; //Some([300, 300]);
     let buf = capture_window_ex(hwnd, using, area, crop_xy, crop_wh).unwrap();
+
+    // Same settings with a builder
+    let options = CaptureOptions::new().using(using).area(area);
+    let buf = capture_window_with(hwnd, &options).unwrap();
+    let img = RgbaImage::from_raw(buf.width, buf.height, buf.pixels).unwrap();
+    img.save("screenshot_options.jpg").unwrap();
 }
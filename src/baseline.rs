@@ -0,0 +1,88 @@
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+
+use crate::capture::{capture_window, RgbBuf, WSError};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiffStatus {
+    Match,
+    Mismatch,
+    // there was no baseline, the capture was written as the new one
+    BaselineCreated,
+}
+
+#[derive(Debug)]
+pub struct DiffReport {
+    pub status: DiffStatus,
+    // fraction of differing pixels, 1.0 if the sizes differ
+    pub diff_ratio: f32,
+    // on Mismatch with equal sizes: the capture darkened, differing pixels red
+    pub diff_image: Option<RgbBuf>,
+}
+
+// Captures the window and compares it to the image at baseline_path with
+// diff_ratio_tolerant, tolerance is per channel. It matches only if no pixel
+// differs beyond the tolerance. If the baseline doesn't exist and
+// create_missing is set the capture is saved there as PNG.
+pub fn capture_window_assert_matches(
+    hwnd: isize,
+    baseline_path: &Path,
+    tolerance: u8,
+    create_missing: bool,
+) -> Result<DiffReport, WSError> {
+    let capture = capture_window(hwnd)?;
+
+    if create_missing && !baseline_path.exists() {
+        let mut file = BufWriter::new(File::create(baseline_path)?);
+        capture.write_png(&mut file)?;
+        return Ok(DiffReport {
+            status: DiffStatus::BaselineCreated,
+            diff_ratio: 0.0,
+            diff_image: None,
+        });
+    }
+
+    let baseline = image::open(baseline_path)
+        .map_err(|_| WSError::DecodeError)?
+        .to_rgba8();
+    let baseline = RgbBuf {
+        width: baseline.width(),
+        height: baseline.height(),
+        pixels: baseline.into_raw(),
+    };
+
+    let Some(diff_ratio) = capture.diff_ratio_tolerant(&baseline, tolerance) else {
+        return Ok(DiffReport {
+            status: DiffStatus::Mismatch,
+            diff_ratio: 1.0,
+            diff_image: None,
+        });
+    };
+    if diff_ratio == 0.0 {
+        return Ok(DiffReport {
+            status: DiffStatus::Match,
+            diff_ratio,
+            diff_image: None,
+        });
+    }
+
+    let mut diff_image = capture;
+    for (p, b) in diff_image
+        .pixels
+        .chunks_exact_mut(4)
+        .zip(baseline.pixels.chunks_exact(4))
+    {
+        let differs = (0..3).any(|c| p[c].abs_diff(b[c]) > tolerance);
+        let marked = match differs {
+            true => [255, 0, 0, 255],
+            false => [p[0] / 3, p[1] / 3, p[2] / 3, 255],
+        };
+        p.copy_from_slice(&marked);
+    }
+    Ok(DiffReport {
+        status: DiffStatus::Mismatch,
+        diff_ratio,
+        diff_image: Some(diff_image),
+    })
+}
@@ -0,0 +1,693 @@
+use std::io::{self, Write};
+
+use crate::capture::{BgraBuf, PixelFormat, RgbBuf, WSError};
+use crate::font::{glyph, GLYPH_HEIGHT, GLYPH_WIDTH};
+use crate::geometry::WindowRect;
+
+// Byte layout of RgbBuf::write_raw, no header or row padding in any format.
+// Rows are written top to bottom unless the name says BottomUp (the BMP
+// order), pixels left to right. The ffmpeg -pix_fmt names are rgba, bgra,
+// rgb24 and bgr24.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RawFormat {
+    // 4 bytes per pixel: R, G, B, A
+    Rgba,
+    // 4 bytes per pixel: B, G, R, A
+    Bgra,
+    // 3 bytes per pixel: R, G, B
+    Rgb,
+    // 3 bytes per pixel: B, G, R
+    Bgr,
+    // Bgra with the bottom row first
+    BgraBottomUp,
+    // Bgr with the bottom row first
+    BgrBottomUp,
+}
+
+// header of to_bytes: magic, width and height as little endian u32, format
+const BYTES_MAGIC: &[u8; 4] = b"WSRB";
+pub(crate) const BYTES_HEADER_LEN: usize = 13;
+// format byte, the only one so far is RGBA
+const BYTES_FORMAT_RGBA: u8 = 0;
+
+// clockwise
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rotation {
+    Deg90,
+    Deg180,
+    Deg270,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum RedactMode {
+    Fill([u8; 3]),
+    Blur(u32),
+    Pixelate(u32),
+}
+
+// dark to light, for a light on dark terminal
+const ASCII_RAMP: &[u8] = b" .:-=+*#%@";
+
+// IEC 61966-2-1
+fn srgb_to_linear(value: u8) -> f32 {
+    let c = value as f32 / 255.0;
+    match c <= 0.04045 {
+        true => c / 12.92,
+        false => ((c + 0.055) / 1.055).powf(2.4),
+    }
+}
+
+// RgbBuf::content_hash for pixels that aren't in an RgbBuf
+pub(crate) fn content_hash(pixels: &[u8], width: u32, height: u32) -> u64 {
+    const SEED: u64 = 0x51_7c_c1_b7_27_22_0a_95;
+    let mix = |hash: u64, word: u64| (hash.rotate_left(5) ^ word).wrapping_mul(SEED);
+
+    let mut hash = mix(mix(0, width as u64), height as u64);
+    let mut chunks = pixels.chunks_exact(8);
+    for chunk in &mut chunks {
+        hash = mix(hash, u64::from_le_bytes(chunk.try_into().unwrap()));
+    }
+    for &byte in chunks.remainder() {
+        hash = mix(hash, byte as u64);
+    }
+    hash
+}
+
+// true if every 4 byte pixel is the same, works for RGBA and BGRA alike
+pub(crate) fn is_uniform(pixels: &[u8]) -> bool {
+    let mut pixels = pixels.chunks_exact(4);
+    match pixels.next() {
+        Some(first) => pixels.all(|p| p == first),
+        None => true,
+    }
+}
+
+impl RgbBuf {
+    // true if every pixel has the same value, e.g. the all black frame
+    // PrintWindow returns for GPU-accelerated or overlay windows
+    pub fn is_blank(&self) -> bool {
+        is_uniform(&self.pixels)
+    }
+
+    // FxHash over the raw pixel bytes plus the dimensions. This is a content
+    // hash, not a perceptual one: frames that look the same but differ in a
+    // single byte hash differently.
+    pub fn content_hash(&self) -> u64 {
+        content_hash(&self.pixels, self.width, self.height)
+    }
+
+    // Alpha-blends top onto self with its top left corner at (x, y). The
+    // per-pixel alpha of top is multiplied by alpha (0.0..=1.0), the part of
+    // top outside self is ignored.
+    pub fn overlay(&mut self, top: &RgbBuf, x: i32, y: i32, alpha: f32) {
+        let alpha = alpha.clamp(0.0, 1.0);
+        let rect = WindowRect {
+            x,
+            y,
+            width: top.width as i32,
+            height: top.height as i32,
+        };
+        let Some([x0, y0, x1, y1]) = self.clamp_rect(rect) else {
+            return;
+        };
+        for row in y0..y1 {
+            for col in x0..x1 {
+                let d = self.index(col, row);
+                let s = top.index((col as i32 - x) as usize, (row as i32 - y) as usize);
+                let a = top.pixels[s + 3] as f32 / 255.0 * alpha;
+                for c in 0..3 {
+                    let blended =
+                        top.pixels[s + c] as f32 * a + self.pixels[d + c] as f32 * (1.0 - a);
+                    self.pixels[d + c] = blended.round() as u8;
+                }
+                let out_alpha = a * 255.0 + self.pixels[d + 3] as f32 * (1.0 - a);
+                self.pixels[d + 3] = out_alpha.round() as u8;
+            }
+        }
+    }
+
+    // Draws text with the bundled 5x7 font, (x, y) is the top left corner of
+    // the first glyph. '\n' starts a new line, glyphs outside the buffer are
+    // clipped.
+    pub fn draw_text(&mut self, text: &str, x: i32, y: i32, color: [u8; 3]) {
+        let (mut gx, mut gy) = (x as i64, y as i64);
+        for c in text.chars() {
+            if c == '\n' {
+                gx = x as i64;
+                gy += GLYPH_HEIGHT as i64 + 1;
+                continue;
+            }
+            for (col, bits) in glyph(c).iter().enumerate() {
+                for row in 0..GLYPH_HEIGHT {
+                    if bits >> row & 1 == 0 {
+                        continue;
+                    }
+                    let (px, py) = (gx + col as i64, gy + row as i64);
+                    if px < 0 || py < 0 || px >= self.width as i64 || py >= self.height as i64 {
+                        continue;
+                    }
+                    let i = self.index(px as usize, py as usize);
+                    self.pixels[i..i + 3].copy_from_slice(&color);
+                }
+            }
+            gx += GLYPH_WIDTH as i64 + 1;
+        }
+    }
+
+    // Box filter: every target pixel is the average of the source pixels it
+    // covers. Meant for downscaling, upscaling repeats pixels.
+    pub fn resize(&self, width: u32, height: u32) -> RgbBuf {
+        let (sw, sh) = (self.width as usize, self.height as usize);
+        let (dw, dh) = (width as usize, height as usize);
+        if sw == 0 || sh == 0 {
+            return RgbBuf {
+                pixels: vec![0; dw * dh * 4],
+                width,
+                height,
+            };
+        }
+        let mut pixels = Vec::with_capacity(dw * dh * 4);
+        for y in 0..dh {
+            let y0 = y * sh / dh;
+            let y1 = ((y + 1) * sh / dh).max(y0 + 1);
+            for x in 0..dw {
+                let x0 = x * sw / dw;
+                let x1 = ((x + 1) * sw / dw).max(x0 + 1);
+                let mut sum = [0u64; 4];
+                for sy in y0..y1 {
+                    for sx in x0..x1 {
+                        let i = self.index(sx, sy);
+                        (0..4).for_each(|c| sum[c] += self.pixels[i + c] as u64);
+                    }
+                }
+                let n = ((x1 - x0) * (y1 - y0)) as u64;
+                pixels.extend(sum.map(|c| (c / n) as u8));
+            }
+        }
+        RgbBuf {
+            pixels,
+            width,
+            height,
+        }
+    }
+
+    // pixels in B, G, R, A order, see capture_window_into_bgra
+    pub fn into_bgra(mut self) -> Vec<u8> {
+        self.pixels.chunks_exact_mut(4).for_each(|c| c.swap(0, 2));
+        self.pixels
+    }
+
+    pub fn format(&self) -> PixelFormat {
+        PixelFormat::Rgba
+    }
+
+    pub fn to_bgra(self) -> BgraBuf {
+        let (width, height) = (self.width, self.height);
+        BgraBuf {
+            pixels: self.into_bgra(),
+            width,
+            height,
+        }
+    }
+
+    // rect is in buffer coordinates, the part outside the buffer is ignored
+    pub fn redact(&mut self, rect: WindowRect, mode: RedactMode) {
+        let Some([x0, y0, x1, y1]) = self.clamp_rect(rect) else {
+            return;
+        };
+        match mode {
+            RedactMode::Fill(color) => {
+                for y in y0..y1 {
+                    for x in x0..x1 {
+                        let i = self.index(x, y);
+                        self.pixels[i..i + 3].copy_from_slice(&color);
+                    }
+                }
+            }
+            RedactMode::Pixelate(block) => {
+                let block = block.max(1) as usize;
+                for by in (y0..y1).step_by(block) {
+                    for bx in (x0..x1).step_by(block) {
+                        let (bx1, by1) = ((bx + block).min(x1), (by + block).min(y1));
+                        let mut sum = [0u64; 3];
+                        for y in by..by1 {
+                            for x in bx..bx1 {
+                                let i = self.index(x, y);
+                                (0..3).for_each(|c| sum[c] += self.pixels[i + c] as u64);
+                            }
+                        }
+                        let n = ((bx1 - bx) * (by1 - by)) as u64;
+                        let color = sum.map(|c| (c / n) as u8);
+                        for y in by..by1 {
+                            for x in bx..bx1 {
+                                let i = self.index(x, y);
+                                self.pixels[i..i + 3].copy_from_slice(&color);
+                            }
+                        }
+                    }
+                }
+            }
+            RedactMode::Blur(radius) => {
+                let radius = radius as usize;
+                // separable box blur, horizontal pass then vertical pass
+                for y in y0..y1 {
+                    let line: Vec<usize> = (x0..x1).map(|x| self.index(x, y)).collect();
+                    self.box_blur_line(&line, radius);
+                }
+                for x in x0..x1 {
+                    let line: Vec<usize> = (y0..y1).map(|y| self.index(x, y)).collect();
+                    self.box_blur_line(&line, radius);
+                }
+            }
+        }
+    }
+
+    // exact comparison including alpha, false if the sizes differ
+    pub fn pixels_equal(&self, other: &RgbBuf) -> bool {
+        self.width == other.width && self.height == other.height && self.pixels == other.pixels
+    }
+
+    // number of differing pixels, None if the sizes differ. With ignore_alpha
+    // only the RGB channels are compared.
+    pub fn diff_count(&self, other: &RgbBuf, ignore_alpha: bool) -> Option<u64> {
+        if self.width != other.width || self.height != other.height {
+            return None;
+        }
+        let channels = if ignore_alpha { 3 } else { 4 };
+        let count = self
+            .pixels
+            .chunks_exact(4)
+            .zip(other.pixels.chunks_exact(4))
+            .filter(|(a, b)| a[..channels] != b[..channels])
+            .count();
+        Some(count as u64)
+    }
+
+    // diff_count over RGB, skipping pixels inside any of mask (buffer
+    // coordinates), e.g. a clock or a spinner that changes on every capture
+    pub fn diff_count_masked(&self, other: &RgbBuf, mask: &[WindowRect]) -> Option<u64> {
+        if self.width != other.width || self.height != other.height {
+            return None;
+        }
+        let mut masked = vec![false; (self.width * self.height) as usize];
+        for [x0, y0, x1, y1] in mask.iter().filter_map(|&r| self.clamp_rect(r)) {
+            for y in y0..y1 {
+                let row = y * self.width as usize;
+                masked[row + x0..row + x1].fill(true);
+            }
+        }
+        let count = self
+            .pixels
+            .chunks_exact(4)
+            .zip(other.pixels.chunks_exact(4))
+            .zip(masked)
+            .filter(|((a, b), masked)| !masked && a[..3] != b[..3])
+            .count();
+        Some(count as u64)
+    }
+
+    // fraction of pixels where any RGB channel differs by more than
+    // per_channel_tol, None if the sizes differ. A tolerance of 0 is an exact
+    // RGB comparison, the same as diff_count with ignore_alpha.
+    pub fn diff_ratio_tolerant(&self, other: &RgbBuf, per_channel_tol: u8) -> Option<f32> {
+        if self.width != other.width || self.height != other.height {
+            return None;
+        }
+        let total = self.width as u64 * self.height as u64;
+        if total == 0 {
+            return Some(0.0);
+        }
+        let changed = self
+            .pixels
+            .chunks_exact(4)
+            .zip(other.pixels.chunks_exact(4))
+            .filter(|(a, b)| (0..3).any(|c| a[c].abs_diff(b[c]) > per_channel_tol))
+            .count();
+        Some(changed as f32 / total as f32)
+    }
+
+    // Trims border rows and columns whose pixels all match bg within tol on
+    // every channel. An image that is background only is returned unchanged.
+    pub fn autocrop(&self, bg: [u8; 3], tol: u8) -> RgbBuf {
+        let rect = self.detect_content_rect(bg, tol);
+        if rect.is_empty() {
+            return RgbBuf {
+                pixels: self.pixels.clone(),
+                width: self.width,
+                height: self.height,
+            };
+        }
+        let (x0, x1) = (rect.x as usize, rect.right() as usize);
+        let mut pixels = Vec::with_capacity(rect.area() as usize * 4);
+        for y in rect.y as usize..rect.bottom() as usize {
+            pixels.extend_from_slice(&self.pixels[self.index(x0, y)..self.index(x1, y)]);
+        }
+        RgbBuf {
+            pixels,
+            width: rect.width as u32,
+            height: rect.height as u32,
+        }
+    }
+
+    // The rectangle autocrop would keep: everything inside the outermost
+    // rows and columns that have a pixel differing from bg by more than tol,
+    // e.g. a video without its letterbox bars. An empty rect at 0, 0 if the
+    // image is background only.
+    pub fn detect_content_rect(&self, bg: [u8; 3], tol: u8) -> WindowRect {
+        let is_bg = |x: usize, y: usize| {
+            let i = self.index(x, y);
+            (0..3).all(|c| self.pixels[i + c].abs_diff(bg[c]) <= tol)
+        };
+        let (w, h) = (self.width as usize, self.height as usize);
+
+        let Some(y0) = (0..h).find(|&y| (0..w).any(|x| !is_bg(x, y))) else {
+            return WindowRect::default();
+        };
+        // a content row exists, so the searches below always succeed
+        let y1 = (y0..h)
+            .rev()
+            .find(|&y| (0..w).any(|x| !is_bg(x, y)))
+            .unwrap()
+            + 1;
+        let x0 = (0..w).find(|&x| (y0..y1).any(|y| !is_bg(x, y))).unwrap();
+        let x1 = (x0..w)
+            .rev()
+            .find(|&x| (y0..y1).any(|y| !is_bg(x, y)))
+            .unwrap()
+            + 1;
+
+        WindowRect {
+            x: x0 as i32,
+            y: y0 as i32,
+            width: (x1 - x0) as i32,
+            height: (y1 - y0) as i32,
+        }
+    }
+
+    // writes only the pixel bytes, see RawFormat for the layout
+    pub fn write_raw<W: Write>(&self, w: &mut W, format: RawFormat) -> io::Result<()> {
+        let row_len = self.width as usize * 4;
+        if row_len == 0 {
+            return Ok(());
+        }
+        let rows = self.pixels.chunks_exact(row_len);
+        let rows: Box<dyn Iterator<Item = &[u8]>> = match format {
+            RawFormat::BgraBottomUp | RawFormat::BgrBottomUp => Box::new(rows.rev()),
+            _ => Box::new(rows),
+        };
+
+        let mut line = Vec::with_capacity(row_len);
+        for row in rows {
+            line.clear();
+            for p in row.chunks_exact(4) {
+                match format {
+                    RawFormat::Rgba => line.extend_from_slice(p),
+                    RawFormat::Bgra | RawFormat::BgraBottomUp => {
+                        line.extend_from_slice(&[p[2], p[1], p[0], p[3]])
+                    }
+                    RawFormat::Rgb => line.extend_from_slice(&p[..3]),
+                    RawFormat::Bgr | RawFormat::BgrBottomUp => {
+                        line.extend_from_slice(&[p[2], p[1], p[0]])
+                    }
+                }
+            }
+            w.write_all(&line)?;
+        }
+        Ok(())
+    }
+
+    // (x, y, rgba) of every step-th pixel in both directions, starting at the
+    // top left corner, in RgbBuf's RGBA order. A step of 0 is treated as 1.
+    pub fn sub_sample(&self, step: u32) -> impl Iterator<Item = (u32, u32, [u8; 4])> + '_ {
+        let step = step.max(1) as usize;
+        (0..self.height).step_by(step).flat_map(move |y| {
+            (0..self.width).step_by(step).map(move |x| {
+                let i = self.index(x as usize, y as usize);
+                let p = &self.pixels[i..i + 4];
+                (x, y, [p[0], p[1], p[2], p[3]])
+            })
+        })
+    }
+
+    // Rotates clockwise, width and height swap for the quarter turns
+    pub fn rotate(&self, rotation: Rotation) -> RgbBuf {
+        let (w, h) = (self.width as usize, self.height as usize);
+        let (width, height) = match rotation {
+            Rotation::Deg180 => (self.width, self.height),
+            Rotation::Deg90 | Rotation::Deg270 => (self.height, self.width),
+        };
+        let mut pixels = Vec::with_capacity(self.pixels.len());
+        for y in 0..height as usize {
+            for x in 0..width as usize {
+                // the source pixel that lands on x, y
+                let (sx, sy) = match rotation {
+                    Rotation::Deg90 => (y, h - 1 - x),
+                    Rotation::Deg180 => (w - 1 - x, h - 1 - y),
+                    Rotation::Deg270 => (w - 1 - y, x),
+                };
+                let i = self.index(sx, sy);
+                pixels.extend_from_slice(&self.pixels[i..i + 4]);
+            }
+        }
+        RgbBuf {
+            pixels,
+            width,
+            height,
+        }
+    }
+
+    // Per channel counts of each intensity as [r, g, b]. Pixels are read in
+    // RgbBuf's RGBA order, alpha is not counted.
+    pub fn histogram(&self) -> [[u32; 256]; 3] {
+        let mut histogram = [[0u32; 256]; 3];
+        for p in self.pixels.chunks_exact(4) {
+            histogram[0][p[0] as usize] += 1;
+            histogram[1][p[1] as usize] += 1;
+            histogram[2][p[2] as usize] += 1;
+        }
+        histogram
+    }
+
+    // Arithmetic mean of the RGB channels taken directly on the sRGB values,
+    // no linearization. Alpha is ignored, black for an empty buffer.
+    pub fn average_color(&self) -> [u8; 3] {
+        let mut sum = [0u64; 3];
+        for p in self.pixels.chunks_exact(4) {
+            (0..3).for_each(|c| sum[c] += p[c] as u64);
+        }
+        let n = (self.pixels.len() / 4).max(1) as u64;
+        sum.map(|c| (c / n) as u8)
+    }
+
+    // Quantizes every channel into buckets levels (clamped to 1-64, there are
+    // buckets^3 bins) and returns the mean color of the most populated bin.
+    // Alpha is ignored, black for an empty buffer.
+    pub fn dominant_color(&self, buckets: u32) -> [u8; 3] {
+        let buckets = buckets.clamp(1, 64) as usize;
+        let bucket = |v: u8| v as usize * buckets / 256;
+        // [count, r, g, b] sums per bucket
+        let mut bins = vec![[0u64; 4]; buckets * buckets * buckets];
+        for p in self.pixels.chunks_exact(4) {
+            let bin = &mut bins[(bucket(p[0]) * buckets + bucket(p[1])) * buckets + bucket(p[2])];
+            bin[0] += 1;
+            (0..3).for_each(|c| bin[c + 1] += p[c] as u64);
+        }
+        match bins.iter().max_by_key(|bin| bin[0]) {
+            Some(bin) if bin[0] > 0 => [1, 2, 3].map(|c| (bin[c] / bin[0]) as u8),
+            _ => [0; 3],
+        }
+    }
+
+    // R, G, B per pixel as linear light 0.0-1.0, decoded with the sRGB
+    // transfer function. Averaging or blending should happen on these, not
+    // on the sRGB bytes. Alpha is dropped.
+    pub fn to_linear(&self) -> Vec<f32> {
+        let table: Vec<f32> = (0..=255u8).map(srgb_to_linear).collect();
+        self.pixels
+            .chunks_exact(4)
+            .flat_map(|p| [p[0], p[1], p[2]])
+            .map(|c| table[c as usize])
+            .collect()
+    }
+
+    // adds delta to the RGB channels, clamped to 0-255, alpha is untouched
+    pub fn adjust_brightness(&mut self, delta: i16) {
+        for p in self.pixels.chunks_exact_mut(4) {
+            for c in &mut p[..3] {
+                *c = (*c as i16 + delta).clamp(0, 255) as u8;
+            }
+        }
+    }
+
+    // scales the RGB channels away from (factor > 1) or towards (factor < 1)
+    // mid gray, clamped to 0-255, alpha is untouched
+    pub fn adjust_contrast(&mut self, factor: f32) {
+        for p in self.pixels.chunks_exact_mut(4) {
+            for c in &mut p[..3] {
+                *c = ((*c as f32 - 128.0) * factor + 128.0)
+                    .round()
+                    .clamp(0.0, 255.0) as u8;
+            }
+        }
+    }
+
+    // Terminal preview cols characters wide. Rows are halved since character
+    // cells are about twice as tall as wide, luminance picks from ASCII_RAMP.
+    pub fn to_ascii(&self, cols: u32) -> String {
+        if cols == 0 || self.width == 0 || self.height == 0 {
+            return String::new();
+        }
+        let rows = ((self.height as u64 * cols as u64) / (self.width as u64 * 2)).max(1) as u32;
+        let small = self.resize(cols, rows);
+        let mut out = String::with_capacity(((cols + 1) * rows) as usize);
+        for row in small.pixels.chunks_exact(cols as usize * 4) {
+            for p in row.chunks_exact(4) {
+                let luma = (p[0] as u32 * 299 + p[1] as u32 * 587 + p[2] as u32 * 114) / 1000;
+                let i = luma as usize * (ASCII_RAMP.len() - 1) / 255;
+                out.push(ASCII_RAMP[i] as char);
+            }
+            out.push('\n');
+        }
+        out
+    }
+
+    // Lossless dump for IPC or disk: the 13 byte header described at
+    // BYTES_MAGIC followed by the RGBA pixels
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(BYTES_HEADER_LEN + self.pixels.len());
+        bytes.extend_from_slice(&self.bytes_header());
+        bytes.extend_from_slice(&self.pixels);
+        bytes
+    }
+
+    pub(crate) fn bytes_header(&self) -> [u8; BYTES_HEADER_LEN] {
+        let mut header = [0; BYTES_HEADER_LEN];
+        header[..4].copy_from_slice(BYTES_MAGIC);
+        header[4..8].copy_from_slice(&self.width.to_le_bytes());
+        header[8..12].copy_from_slice(&self.height.to_le_bytes());
+        header[12] = BYTES_FORMAT_RGBA;
+        header
+    }
+
+    // reads what to_bytes wrote, DecodeError if the header or the length
+    // don't match
+    pub fn from_bytes(bytes: &[u8]) -> Result<RgbBuf, WSError> {
+        if bytes.len() < BYTES_HEADER_LEN || &bytes[..4] != BYTES_MAGIC {
+            return Err(WSError::DecodeError);
+        }
+        let width = u32::from_le_bytes(bytes[4..8].try_into().unwrap());
+        let height = u32::from_le_bytes(bytes[8..12].try_into().unwrap());
+        if bytes[12] != BYTES_FORMAT_RGBA {
+            return Err(WSError::DecodeError);
+        }
+        let pixels = &bytes[BYTES_HEADER_LEN..];
+        let len = (width as u64 * height as u64).checked_mul(4);
+        if len != Some(pixels.len() as u64) {
+            return Err(WSError::DecodeError);
+        }
+        Ok(RgbBuf {
+            pixels: pixels.to_vec(),
+            width,
+            height,
+        })
+    }
+
+    // copies src into self with its top left corner at (x, y), clipped to self
+    pub(crate) fn blit(&mut self, src: &RgbBuf, x: i32, y: i32) {
+        let rect = WindowRect {
+            x,
+            y,
+            width: src.width as i32,
+            height: src.height as i32,
+        };
+        let Some([x0, y0, x1, y1]) = self.clamp_rect(rect) else {
+            return;
+        };
+        let len = (x1 - x0) * 4;
+        for row in y0..y1 {
+            let d = self.index(x0, row);
+            let s = src.index((x0 as i32 - x) as usize, (row as i32 - y) as usize);
+            self.pixels[d..d + len].copy_from_slice(&src.pixels[s..s + len]);
+        }
+    }
+
+    // fraction of pixels that differ, 1.0 if the sizes don't match
+    pub(crate) fn changed_ratio(&self, other: &RgbBuf) -> f32 {
+        if self.width != other.width || self.height != other.height {
+            return 1.0;
+        }
+        let total = (self.width * self.height) as usize;
+        if total == 0 {
+            return 0.0;
+        }
+        let changed = self
+            .pixels
+            .chunks_exact(4)
+            .zip(other.pixels.chunks_exact(4))
+            .filter(|(a, b)| a != b)
+            .count();
+        changed as f32 / total as f32
+    }
+
+    pub(crate) fn index(&self, x: usize, y: usize) -> usize {
+        (y * self.width as usize + x) * 4
+    }
+
+    // intersection of rect with the buffer as [x0, y0, x1, y1], None if empty
+    pub(crate) fn clamp_rect(&self, rect: WindowRect) -> Option<[usize; 4]> {
+        let bounds = WindowRect {
+            x: 0,
+            y: 0,
+            width: self.width as i32,
+            height: self.height as i32,
+        };
+        rect.intersect(&bounds)
+            .map(|r| [r.x, r.y, r.right(), r.bottom()].map(|v| v as usize))
+    }
+
+    // blurs the pixels at the given offsets as one line, samples past the
+    // ends of the line are clamped to the line
+    fn box_blur_line(&mut self, line: &[usize], radius: usize) {
+        let mut prefix = vec![[0u64; 3]; line.len() + 1];
+        for (n, &i) in line.iter().enumerate() {
+            let sum = prefix[n];
+            prefix[n + 1] = std::array::from_fn(|c| sum[c] + self.pixels[i + c] as u64);
+        }
+        for (n, &i) in line.iter().enumerate() {
+            let lo = n.saturating_sub(radius);
+            let hi = (n + radius + 1).min(line.len());
+            let count = (hi - lo) as u64;
+            let (lo, hi) = (prefix[lo], prefix[hi]);
+            for ((value, hi), lo) in self.pixels[i..i + 3].iter_mut().zip(hi).zip(lo) {
+                *value = ((hi - lo) / count) as u8;
+            }
+        }
+    }
+}
+
+impl BgraBuf {
+    // None if pixels isn't exactly width * height * 4 bytes
+    pub fn from_raw(pixels: Vec<u8>, width: u32, height: u32) -> Option<BgraBuf> {
+        match pixels.len() as u64 == width as u64 * height as u64 * 4 {
+            true => Some(BgraBuf {
+                pixels,
+                width,
+                height,
+            }),
+            false => None,
+        }
+    }
+
+    pub fn format(&self) -> PixelFormat {
+        PixelFormat::Bgra
+    }
+
+    pub fn to_rgba(mut self) -> RgbBuf {
+        self.pixels.chunks_exact_mut(4).for_each(|c| c.swap(0, 2));
+        RgbBuf {
+            pixels: self.pixels,
+            width: self.width,
+            height: self.height,
+        }
+    }
+}
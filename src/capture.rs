@@ -1,45 +1,182 @@
 use std::mem::size_of;
-use windows::Win32::Foundation::{ERROR_INVALID_PARAMETER, E_FAIL, HWND};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+use windows::core::IntoParam;
+use windows::Win32::Foundation::{
+    ERROR_INCORRECT_SIZE, ERROR_INVALID_PARAMETER, ERROR_INVALID_WINDOW_HANDLE, E_FAIL, HWND,
+    LPARAM, POINT, WPARAM,
+};
+use windows::Win32::Graphics::Dwm::DwmFlush;
 use windows::Win32::Graphics::Gdi::{
-    BitBlt, CreateCompatibleBitmap, CreateCompatibleDC, DeleteDC, DeleteObject, GetDC, GetDIBits,
-    ReleaseDC, SelectObject, StretchBlt, BITMAPINFO, BITMAPINFOHEADER, BI_RGB, DIB_RGB_COLORS,
-    SRCCOPY,
+    BitBlt, ClientToScreen, ExcludeClipRect, GetDIBits, GetObjectW, GetPixel, RedrawWindow,
+    SetBrushOrgEx, SetMapMode, SetStretchBltMode, SetViewportExtEx, SetWindowExtEx, StretchBlt,
+    BITMAP, BITMAPINFO, BITMAPINFOHEADER, BI_RGB, COLORONCOLOR, DIB_RGB_COLORS, HALFTONE, HBITMAP,
+    HDC, HRGN, MM_ANISOTROPIC, RDW_ALLCHILDREN, RDW_FRAME, RDW_INVALIDATE, RDW_UPDATENOW, SRCCOPY,
 };
 use windows::Win32::Storage::Xps::{PrintWindow, PRINT_WINDOW_FLAGS, PW_CLIENTONLY};
-use windows::Win32::UI::HiDpi::{SetProcessDpiAwareness, PROCESS_PER_MONITOR_DPI_AWARE};
+#[cfg(target_pointer_width = "64")]
+use windows::Win32::UI::WindowsAndMessaging::GetClassLongPtrW;
+#[cfg(target_pointer_width = "32")]
+use windows::Win32::UI::WindowsAndMessaging::GetClassLongW;
 use windows::Win32::UI::WindowsAndMessaging::{
-    GetSystemMetrics, PW_RENDERFULLCONTENT, SM_CXVIRTUALSCREEN, SM_CYVIRTUALSCREEN,
-    SM_XVIRTUALSCREEN, SM_YVIRTUALSCREEN,
+    GetDlgItem, GetIconInfo, GetWindow, IsIconic, IsWindow, SendMessageTimeoutW, SendMessageW,
+    SetWindowPos, ShowWindow, GCLP_HICON, GCLP_HICONSM, GET_CLASS_LONG_INDEX, GW_HWNDPREV, HICON,
+    HWND_TOP, ICONINFO, ICON_BIG, ICON_SMALL2, PRF_CHILDREN, PRF_CLIENT, PRF_ERASEBKGND,
+    PRF_NONCLIENT, PW_RENDERFULLCONTENT, SMTO_ABORTIFHUNG, SWP_NOACTIVATE, SWP_NOMOVE, SWP_NOSIZE,
+    SW_SHOWMINNOACTIVE, SW_SHOWNOACTIVATE, WM_GETICON, WM_PRINT, WM_PRINTCLIENT,
 };
 
-use crate::wrappers::{CreatedHdc, Hbitmap, Hdc, Rect};
+use crate::buffer::is_uniform;
+use crate::geometry::{VirtualScreenOrigin, WindowRect};
+use crate::monitor::{mixed_dpi_monitors, MonitorInfo};
+use crate::options::CaptureOptions;
+use crate::trace::{trace_event, trace_record, trace_span};
+use crate::utils::{
+    get_class_name, get_dpi_for_window, get_visible_window_rect, get_window_text,
+    get_window_thread_process_id, is_capture_protected, is_cloaked, is_secure_desktop_active,
+    is_window_on_current_desktop, set_dpi_awareness, virtual_screen_bounds,
+};
+use crate::wrappers::{CreatedHdc, Hbitmap, Hdc, Rect, SelectedObject};
 
 #[derive(Debug)]
 pub enum WSError {
     GetDCIsNull,
-    GetClientRectIsZero,
+    // the window rect, the window has one but no client area
+    GetClientRectIsZero(WindowRect),
     CreateCompatibleDCIsNull,
     CreateCompatibleBitmapIsNull,
     SelectObjectError,
     PrintWindowIsZero,
     GetDIBitsError,
     GetSystemMetricsIsZero,
+    GetDpiForWindowIsZero,
+    RegionOutOfBounds,
+    EnumDisplayMonitorsError,
+    MonitorNotFound,
+    ControlNotFound,
+    GetPixelError,
+    EncodeError,
+    DecodeError,
     StretchBltIsZero,
     BitBltError,
+    BlankResult,
+    NoIcon,
+    Timeout,
+    // every method of Using::Auto returned a blank frame
+    LikelyHardwareAccelerated,
+    SizeMismatch,
+    // a requested output width or height of zero or less
+    InvalidSize,
+    // a row stride smaller than width * 4 bytes
+    StrideTooSmall,
+    // the window kept changing size over every retry
+    WindowResizedDuringCapture,
+    DwmCompositionDisabled,
+    // DWM has no content for the window's thumbnail, e.g. it was never shown
+    ThumbnailEmpty,
+    // not a window handle, or the window was destroyed during the capture
+    InvalidWindow,
+    // the window rect has zero width or height, e.g. a hidden tool window
+    EmptyWindowRect,
+    // the monitors the window overlaps, see mixed_dpi_monitors
+    MixedDpiSpan(Vec<MonitorInfo>),
+    // see is_secure_desktop_active
+    SecureDesktopActive,
+    // the window excludes itself from capture, see is_capture_protected
+    CaptureProtected,
+    // the window is on another virtual desktop and would capture as black
+    WindowOnOtherDesktop,
+    ProcessNotFound,
+    // protected or elevated process
+    ProcessAccessDenied,
+    // ffmpeg couldn't be started, usually not installed or not in PATH
+    FfmpegSpawn(std::io::Error),
+    // ffmpeg exited with this code, None if it was killed
+    FfmpegFailed(Option<i32>),
+    Io(std::io::Error),
+    Windows(windows::core::Error),
+}
+
+impl From<std::io::Error> for WSError {
+    fn from(e: std::io::Error) -> Self {
+        WSError::Io(e)
+    }
+}
+
+impl From<windows::core::Error> for WSError {
+    fn from(e: windows::core::Error) -> Self {
+        match e.code() {
+            code if code == ERROR_INVALID_WINDOW_HANDLE.to_hresult() => WSError::InvalidWindow,
+            // see Rect::from_rect
+            code if code == ERROR_INCORRECT_SIZE.to_hresult() => WSError::EmptyWindowRect,
+            _ => WSError::Windows(e),
+        }
+    }
+}
+
+// for the functions that predate WSError and return windows::core::Error,
+// the variant ends up in the message
+impl From<WSError> for windows::core::Error {
+    fn from(e: WSError) -> Self {
+        match e {
+            WSError::Windows(e) => e,
+            WSError::InvalidWindow => windows::core::Error::new(
+                ERROR_INVALID_WINDOW_HANDLE.to_hresult(),
+                "not a window handle".into(),
+            ),
+            WSError::EmptyWindowRect => windows::core::Error::new(
+                ERROR_INCORRECT_SIZE.to_hresult(),
+                "window rect has zero width or height".into(),
+            ),
+            e => windows::core::Error::new(E_FAIL, format!("{:?}", e).as_str().into()),
+        }
+    }
 }
 
-#[derive(Clone, Copy)]
+#[derive(Debug, Clone, Copy)]
 pub enum Area {
     Full,
     ClientOnly,
 }
 
-#[derive(PartialEq, Clone, Copy)]
+#[derive(Debug, PartialEq, Clone, Copy)]
 pub enum Using {
+    // BitBlt from the window's own DC (GetDC(hwnd)), not the screen DC, so
+    // overlapping windows don't end up in the capture. Client area only, and
+    // hardware accelerated content can still come back blank.
     BitBlt,
     PrintWindow,
+    // PrintWindow, and if that fails or comes back blank (DirectComposition
+    // and hardware overlay windows, e.g. Chrome or media players) WmPrint,
+    // then for Area::ClientOnly BitBlt from the window DC, then a BitBlt of
+    // the window's area from the screen after raising it to the top of the
+    // z-order. An error is only returned if the screen fallback fails too.
+    // The screen fallback brings the window to the front for about 50ms and
+    // then puts it back below the window that was above it, which is visible
+    // on screen.
+    // capture_window_with and capture_window_ex report
+    // LikelyHardwareAccelerated if all are blank, the buffer functions and
+    // CaptureSession return the blank frame.
+    Auto,
+    // Sends WM_PRINT (WM_PRINTCLIENT for ClientOnly) asking the window to
+    // paint itself into the memory DC. Fallback for legacy controls that
+    // capture black with PrintWindow, modern windows usually ignore it.
+    WmPrint,
 }
 
+// StretchBlt mode for scaled captures, at 1:1 both give the same result
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub enum StretchQuality {
+    // COLORONCOLOR, drops pixels, aliased but fast
+    #[default]
+    Fast,
+    // HALFTONE, averages pixels, smooth but slower
+    Smooth,
+}
+
+// R, G, B, A bytes. GDI hands out the desktop's sRGB values as they are,
+// the color channels are assumed to be sRGB encoded.
 #[derive(Debug)]
 pub struct RgbBuf {
     pub pixels: Vec<u8>,
@@ -47,16 +184,403 @@ pub struct RgbBuf {
     pub height: u32,
 }
 
+// Same layout as RgbBuf with red and blue swapped, the order GDI, BMP and
+// D3D B8G8R8A8 textures use. Keeping it a separate type means a BGRA buffer
+// can't be handed to something expecting RGBA without an explicit to_rgba.
+#[derive(Debug)]
+pub struct BgraBuf {
+    pub pixels: Vec<u8>,
+    pub width: u32,
+    pub height: u32,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum PixelFormat {
+    Rgba,
+    Bgra,
+}
+
+#[derive(Debug)]
+pub struct RegionCapture {
+    pub buf: RgbBuf,
+    // the part of the requested rect that was actually captured
+    pub rect: WindowRect,
+    pub clipped: bool,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct CaptureBench {
+    pub frames: u32,
+    pub fps: f64,
+    pub avg_latency: Duration,
+    // megabytes (10^6 bytes) of RGBA pixels per second
+    pub mb_per_sec: f64,
+}
+
+// a frame together with the window it was taken from
+#[derive(Debug)]
+pub struct Capture {
+    pub buf: RgbBuf,
+    pub title: String,
+    pub class_name: String,
+    pub process_id: u32,
+    // window rect in screen coordinates
+    pub rect: WindowRect,
+    // DPI of the window / 96, divide pixel sizes by it to get logical ones
+    pub scale_factor: f32,
+}
+
 #[derive(Debug)]
 pub struct WindowSize {
     pub width: u32,
     pub height: u32,
 }
 
+#[derive(Debug)]
+pub struct DisplayCapture {
+    pub width: u32,
+    pub height: u32,
+    // screen position of the first pixel in the buffer
+    pub origin: VirtualScreenOrigin,
+}
+
 pub fn capture_window(hwnd: isize) -> Result<RgbBuf, windows::core::Error> {
     capture_window_ex(hwnd, Using::PrintWindow, Area::Full, None, None)
 }
 
+// Same as capture_window_ex, but treats a uniform (usually all black) frame
+// as a failure instead of handing it back as a successful capture
+pub fn capture_window_strict(hwnd: isize, using: Using, area: Area) -> Result<RgbBuf, WSError> {
+    capture_window_with(
+        hwnd,
+        &CaptureOptions::new().using(using).area(area).strict(true),
+    )
+}
+
+pub fn capture_window_with(hwnd: isize, options: &CaptureOptions) -> Result<RgbBuf, WSError> {
+    Ok(capture_window_bgra_with(hwnd, options)?.to_rgba())
+}
+
+// capture_window_with without the BGRA to RGBA swap, a full pass over the
+// frame that BMP, clipboard and D3D consumers would only have to undo
+pub fn capture_window_bgra_with(hwnd: isize, options: &CaptureOptions) -> Result<BgraBuf, WSError> {
+    check_capturable(hwnd)?;
+    if options.reject_mixed_dpi {
+        if let Some(monitors) = mixed_dpi_monitors(hwnd)? {
+            return Err(WSError::MixedDpiSpan(monitors));
+        }
+    }
+    // only BitBlt can fall back to the window rect
+    if let (Area::ClientOnly, Using::PrintWindow | Using::Auto | Using::WmPrint) =
+        (options.area, options.using)
+    {
+        if let Some(window) = zero_client_rect(HWND(hwnd)) {
+            return Err(WSError::GetClientRectIsZero(window));
+        }
+    }
+    let (crop_xy, crop_wh) = match (options.trim_shadow, options.using, options.area) {
+        (true, Using::PrintWindow | Using::Auto | Using::WmPrint, Area::Full) => {
+            // the crop becomes relative to the visible frame
+            let window = Rect::get_window_rect(HWND(hwnd))?;
+            let frame = get_visible_window_rect(hwnd)?;
+            let [x, y] = options.crop_xy.unwrap_or([0, 0]);
+            let xy = [frame.x - window.left + x, frame.y - window.top + y];
+            let wh = options
+                .crop_wh
+                .unwrap_or([frame.width - x, frame.height - y]);
+            (Some(xy), Some(wh))
+        }
+        _ => (options.crop_xy, options.crop_wh),
+    };
+    let mut pixels = Vec::new();
+    let size = capture_stable(
+        hwnd,
+        &mut pixels,
+        options.using,
+        options.area,
+        crop_xy,
+        crop_wh,
+        &options.exclude,
+    )?
+    .ok_or(WSError::WindowResizedDuringCapture)?;
+    if options.using == Using::Auto && is_uniform(&pixels) {
+        return Err(WSError::LikelyHardwareAccelerated);
+    }
+    if options.strict && is_uniform(&pixels) {
+        return Err(WSError::BlankResult);
+    }
+
+    // PrintWindow ignores the clip region and BitBlt leaves the clipped out
+    // parts undefined, both get the fill color here
+    let bounds = WindowRect {
+        x: 0,
+        y: 0,
+        width: size.width as i32,
+        height: size.height as i32,
+    };
+    let [cx, cy] = crop_xy.unwrap_or([0, 0]);
+    let [r, g, b] = options.exclude_fill;
+    for rect in &options.exclude {
+        let rect = WindowRect {
+            x: rect.x - cx,
+            y: rect.y - cy,
+            ..*rect
+        };
+        let Some(rect) = rect.intersect(&bounds) else {
+            continue;
+        };
+        for y in rect.y..rect.bottom() {
+            for x in rect.x..rect.right() {
+                let i = ((y * bounds.width + x) * 4) as usize;
+                pixels[i..i + 4].copy_from_slice(&[b, g, r, 255]);
+            }
+        }
+    }
+
+    Ok(BgraBuf {
+        pixels,
+        width: size.width,
+        height: size.height,
+    })
+}
+
+// Captures the window like capture_window and reads its title, class, PID
+// and rect right before. If the window is gone afterwards the metadata can't
+// be trusted to belong to the frame and InvalidWindow is returned.
+pub fn capture_window_full(hwnd: isize) -> Result<Capture, WSError> {
+    let handle = HWND(hwnd);
+    if unsafe { IsWindow(handle) } == false {
+        return Err(WSError::InvalidWindow);
+    }
+    set_dpi_awareness();
+    let rect = Rect::get_window_rect(handle)?;
+    let title = get_window_text(handle);
+    let class_name = get_class_name(handle);
+    let (_, process_id) = get_window_thread_process_id(hwnd);
+    let scale_factor = get_dpi_for_window(hwnd)? as f32 / 96.0;
+
+    let buf = capture_window(hwnd)?;
+    if unsafe { IsWindow(handle) } == false {
+        return Err(WSError::InvalidWindow);
+    }
+    Ok(Capture {
+        buf,
+        title,
+        class_name,
+        process_id,
+        scale_factor,
+        rect: WindowRect {
+            x: rect.left,
+            y: rect.top,
+            width: rect.width,
+            height: rect.height,
+        },
+    })
+}
+
+// PrintWindow sends a synchronous paint message and blocks forever on a hung
+// window, so the capture runs on a worker thread. On timeout the worker is
+// abandoned: the calling thread stays responsive, but the GDI resources held
+// by that one capture are leaked until (if ever) the target window responds.
+pub fn capture_window_timeout(
+    hwnd: isize,
+    using: Using,
+    area: Area,
+    timeout: Duration,
+) -> Result<RgbBuf, WSError> {
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        let options = CaptureOptions::new().using(using).area(area);
+        let _ = tx.send(capture_window_with(hwnd, &options));
+    });
+    match rx.recv_timeout(timeout) {
+        Ok(result) => Ok(result?),
+        Err(_) => Err(WSError::Timeout),
+    }
+}
+
+// Minimized windows capture as black. If the window is iconic it gets restored
+// without activation, captured and minimized again. This visibly affects the
+// target window for the duration of the capture and may flicker.
+pub fn capture_window_restored(hwnd: isize) -> Result<RgbBuf, WSError> {
+    let handle = HWND(hwnd);
+    unsafe {
+        if IsIconic(handle) == false {
+            return Ok(capture_window(hwnd)?);
+        }
+        ShowWindow(handle, SW_SHOWNOACTIVATE);
+        // the restored window hasn't painted yet, paint it now and wait for
+        // DWM to compose the frame so PrintWindow doesn't read a stale one
+        RedrawWindow(
+            handle,
+            None,
+            HRGN::default(),
+            RDW_INVALIDATE | RDW_UPDATENOW | RDW_ALLCHILDREN | RDW_FRAME,
+        );
+        let _ = DwmFlush();
+        let result = capture_window(hwnd);
+        // minimize again whether the capture succeeded or not
+        ShowWindow(handle, SW_SHOWMINNOACTIVE);
+        Ok(result?)
+    }
+}
+
+// Captures every window and lays them out left to right, top to bottom in a
+// grid with the given number of columns. Cells are sized to the largest
+// window, smaller captures are padded with black. A window that fails to
+// capture is drawn as a gray placeholder cell; only if every capture fails
+// the first error is returned.
+pub fn capture_windows_tiled(hwnds: &[isize], columns: u32) -> Result<RgbBuf, WSError> {
+    const PLACEHOLDER: [u8; 4] = [64, 64, 64, 255];
+
+    let columns = columns.max(1) as usize;
+    let captures: Vec<_> = hwnds.iter().map(|&hwnd| capture_window(hwnd)).collect();
+
+    let cell_w = captures.iter().flatten().map(|b| b.width).max();
+    let cell_h = captures.iter().flatten().map(|b| b.height).max();
+    let (cell_w, cell_h) = match (cell_w, cell_h) {
+        (Some(w), Some(h)) => (w, h),
+        _ => {
+            return match captures.into_iter().find_map(Result::err) {
+                Some(e) => Err(e.into()),
+                None => Ok(RgbBuf {
+                    pixels: vec![],
+                    width: 0,
+                    height: 0,
+                }),
+            }
+        }
+    };
+
+    let rows = captures.len().div_ceil(columns);
+    let width = cell_w * columns.min(captures.len()) as u32;
+    let height = cell_h * rows as u32;
+    let mut tiled = RgbBuf {
+        pixels: [0, 0, 0, 255].repeat((width * height) as usize),
+        width,
+        height,
+    };
+
+    for (i, capture) in captures.iter().enumerate() {
+        let x = ((i % columns) as u32 * cell_w) as i32;
+        let y = ((i / columns) as u32 * cell_h) as i32;
+        match capture {
+            Ok(buf) => tiled.blit(buf, x, y),
+            Err(_) => {
+                let placeholder = RgbBuf {
+                    pixels: PLACEHOLDER.repeat((cell_w * cell_h) as usize),
+                    width: cell_w,
+                    height: cell_h,
+                };
+                tiled.blit(&placeholder, x, y);
+            }
+        }
+    }
+
+    Ok(tiled)
+}
+
+// captures the child of a dialog with the given control id
+pub fn capture_control(parent: isize, control_id: i32) -> Result<RgbBuf, WSError> {
+    let control = unsafe { GetDlgItem(HWND(parent), control_id) };
+    if control == HWND(0) {
+        return Err(WSError::ControlNotFound);
+    }
+    Ok(capture_window(control.0)?)
+}
+
+// Captures only the window chrome (title bar, borders, menu bar): the whole
+// window with the client area made fully transparent (alpha 0). The client
+// rect is what the window reports, so windows that custom-draw their frame
+// inside the client area (extended frames, most browsers) come back with
+// little or nothing but the resize border.
+pub fn capture_window_non_client(hwnd: isize) -> Result<RgbBuf, WSError> {
+    let mut buf = capture_window_ex(hwnd, Using::PrintWindow, Area::Full, None, None)?;
+    let client = client_rect_in_window(HWND(hwnd))?;
+    if let Some([x0, y0, x1, y1]) = buf.clamp_rect(client) {
+        for y in y0..y1 {
+            for x in x0..x1 {
+                let i = buf.index(x, y);
+                buf.pixels[i..i + 4].fill(0);
+            }
+        }
+    }
+    Ok(buf)
+}
+
+// The full window and its client area from a single PrintWindow, so both
+// show the same frame. The client image is cut out of the full one.
+pub fn capture_window_full_and_client(hwnd: isize) -> Result<(RgbBuf, RgbBuf), WSError> {
+    let full = capture_window_ex(hwnd, Using::PrintWindow, Area::Full, None, None)?;
+    let client = client_rect_in_window(HWND(hwnd))?;
+    let mut pixels = Vec::new();
+    let (width, height) = match full.clamp_rect(client) {
+        Some([x0, y0, x1, y1]) => {
+            pixels.reserve((x1 - x0) * (y1 - y0) * 4);
+            for y in y0..y1 {
+                pixels.extend_from_slice(&full.pixels[full.index(x0, y)..full.index(x1, y)]);
+            }
+            ((x1 - x0) as u32, (y1 - y0) as u32)
+        }
+        None => (0, 0),
+    };
+    let client = RgbBuf {
+        pixels,
+        width,
+        height,
+    };
+    Ok((full, client))
+}
+
+// GetDC(0) is the screen DC, without this check a zero or stale handle (e.g.
+// from a failed lookup) would capture the screen instead of failing. As a
+// WSError this is InvalidWindow.
+pub(crate) fn check_window(hwnd: isize) -> Result<(), windows::core::Error> {
+    if hwnd == 0 || unsafe { IsWindow(HWND(hwnd)) } == false {
+        return Err(windows::core::Error::new(
+            ERROR_INVALID_WINDOW_HANDLE.to_hresult(),
+            "not a window handle".into(),
+        ));
+    }
+    Ok(())
+}
+
+// Checks every window capture runs first, for errors that would otherwise
+// come back as an opaque GDI failure or a black frame
+pub(crate) fn check_capturable(hwnd: isize) -> Result<(), WSError> {
+    check_window(hwnd)?;
+    if is_secure_desktop_active() {
+        return Err(WSError::SecureDesktopActive);
+    }
+    if is_capture_protected(hwnd) {
+        return Err(WSError::CaptureProtected);
+    }
+    // only cloaked windows can be on another desktop, skips COM otherwise.
+    // If the virtual desktop query fails the capture is attempted anyway.
+    if is_cloaked(HWND(hwnd)) && !is_window_on_current_desktop(hwnd).unwrap_or(true) {
+        return Err(WSError::WindowOnOtherDesktop);
+    }
+    Ok(())
+}
+
+// client area relative to the top left corner of the window rect
+pub(crate) fn client_rect_in_window(hwnd: HWND) -> Result<WindowRect, windows::core::Error> {
+    let window = Rect::get_window_rect(hwnd)?;
+    let client = Rect::get_client_rect(hwnd)?;
+    let mut origin = POINT::default();
+    unsafe {
+        if ClientToScreen(hwnd, &mut origin) == false {
+            return Err(windows::core::Error::from_win32());
+        }
+    }
+    Ok(WindowRect {
+        x: origin.x - window.left,
+        y: origin.y - window.top,
+        width: client.width,
+        height: client.height,
+    })
+}
+
 pub fn capture_window_into_buffer(
     hwnd: isize,
     buffer: &mut Vec<u8>,
@@ -64,15 +588,62 @@ pub fn capture_window_into_buffer(
     capture_window_into_buffer_ex(hwnd, buffer, Using::PrintWindow, Area::Full, None, None)
 }
 
+// Fills buffer with 4 bytes per pixel in B, G, R, A order, rows top to
+// bottom. That's DXGI_FORMAT_B8G8R8A8_UNORM, so it can be uploaded to a
+// D3D11/D2D texture as is. The RGBA functions swap red and blue afterwards.
+pub fn capture_window_into_bgra(
+    hwnd: isize,
+    buffer: &mut Vec<u8>,
+) -> Result<WindowSize, windows::core::Error> {
+    capture_window_into_bgr_buffer_ex(hwnd, buffer, Using::PrintWindow, Area::Full, None, None)
+}
+
+// BGRA with every row starting stride bytes after the previous one, for GPU
+// uploads or encoders that need e.g. 256 byte aligned rows. The padding
+// after each row is zeroed. Fails with StrideTooSmall if stride is less
+// than width * 4.
+pub fn capture_window_into_strided_buffer(
+    hwnd: isize,
+    buffer: &mut Vec<u8>,
+    stride: usize,
+) -> Result<WindowSize, WSError> {
+    let size = capture_window_into_bgra(hwnd, buffer)?;
+    let row_len = size.width as usize * 4;
+    if stride < row_len {
+        return Err(WSError::StrideTooSmall);
+    }
+    if stride == row_len {
+        return Ok(size);
+    }
+    buffer.resize(stride * size.height as usize, 0);
+    // back to front so no row is overwritten before it has been moved
+    for y in (0..size.height as usize).rev() {
+        buffer.copy_within(y * row_len..(y + 1) * row_len, y * stride);
+        buffer[y * stride + row_len..(y + 1) * stride].fill(0);
+    }
+    Ok(size)
+}
+
+pub fn capture_window_bgra(hwnd: isize) -> Result<BgraBuf, windows::core::Error> {
+    let mut pixels = Vec::new();
+    let size = capture_window_into_bgra(hwnd, &mut pixels)?;
+    Ok(BgraBuf {
+        pixels,
+        width: size.width,
+        height: size.height,
+    })
+}
+
 pub fn capture_window_into_bgr_buffer(
     hwnd: isize,
     buffer: &mut Vec<u8>,
 ) -> Result<WindowSize, windows::core::Error> {
+    check_window(hwnd)?;
     buffer.clear();
     let hwnd = HWND(hwnd);
 
     unsafe {
-        let _ = SetProcessDpiAwareness(PROCESS_PER_MONITOR_DPI_AWARE);
+        set_dpi_awareness();
 
         let hdc_screen = Hdc::get_dc(hwnd)?;
 
@@ -81,10 +652,7 @@ pub fn capture_window_into_bgr_buffer(
 
         let hdc = CreatedHdc::create_compatible_dc(hdc_screen.hdc)?;
         let hbmp = Hbitmap::create_compatible_bitmap(hdc_screen.hdc, rect.width, rect.height)?;
-
-        if SelectObject(hdc.hdc, hbmp.hbitmap).is_invalid() {
-            return Err(windows::core::Error::from_win32());
-        }
+        let selected = SelectedObject::select(&hdc, &hbmp)?;
 
         let flags = PRINT_WINDOW_FLAGS(PW_RENDERFULLCONTENT);
 
@@ -92,6 +660,8 @@ pub fn capture_window_into_bgr_buffer(
             return Err(windows::core::Error::from_win32());
         }
 
+        drop(selected);
+
         let bmih = BITMAPINFOHEADER {
             biSize: size_of::<BITMAPINFOHEADER>() as u32,
             biPlanes: 1,
@@ -118,12 +688,16 @@ pub fn capture_window_into_bgr_buffer(
         if gdb == 0 || gdb == ERROR_INVALID_PARAMETER.0 as i32 {
             return Err(windows::core::Error::new(E_FAIL, "GetDIBits error".into()));
         }
+        buffer.set_len((4 * rect.width * rect.height) as usize);
         Ok(WindowSize {
             width: rect.width as u32,
             height: rect.height as u32,
         })
     }
 }
+
+// capture_window_with with these options. The WSError is turned into a
+// windows::core::Error, capture_window_with returns it as is.
 pub fn capture_window_ex(
     hwnd: isize,
     using: Using,
@@ -131,14 +705,14 @@ pub fn capture_window_ex(
     crop_xy: Option<[i32; 2]>,
     crop_wh: Option<[i32; 2]>,
 ) -> Result<RgbBuf, windows::core::Error> {
-    let mut buffer = vec![];
-    let WindowSize { width, height } =
-        capture_window_into_buffer_ex(hwnd, &mut buffer, using, area, crop_xy, crop_wh)?;
-    Ok(RgbBuf {
-        pixels: buffer,
-        width,
-        height,
-    })
+    let options = CaptureOptions {
+        using,
+        area,
+        crop_xy,
+        crop_wh,
+        ..CaptureOptions::default()
+    };
+    Ok(capture_window_with(hwnd, &options)?)
 }
 
 pub fn capture_window_into_buffer_ex(
@@ -162,24 +736,109 @@ pub fn capture_window_into_bgr_buffer_ex(
     crop_xy: Option<[i32; 2]>,
     crop_wh: Option<[i32; 2]>,
 ) -> Result<WindowSize, windows::core::Error> {
+    check_capturable(hwnd)?;
+    capture_stable(hwnd, buffer, using, area, crop_xy, crop_wh, &[])?
+        .ok_or_else(|| windows::core::Error::new(E_FAIL, "window resized during capture".into()))
+}
+
+// Windows that animate their size can change between measuring and
+// blitting, the capture is redone up to RESIZE_RETRIES times. None if the
+// size never settled.
+const RESIZE_RETRIES: usize = 3;
+
+fn capture_stable(
+    hwnd: isize,
+    buffer: &mut Vec<u8>,
+    using: Using,
+    area: Area,
+    crop_xy: Option<[i32; 2]>,
+    crop_wh: Option<[i32; 2]>,
+    exclude: &[WindowRect],
+) -> Result<Option<WindowSize>, windows::core::Error> {
+    check_window(hwnd)?;
+    if using == Using::Auto {
+        // the window DC only covers the client area, for Area::Full it would
+        // return a differently sized frame
+        let chain: &[Using] = match area {
+            Area::Full => &[Using::PrintWindow, Using::WmPrint],
+            Area::ClientOnly => &[Using::PrintWindow, Using::WmPrint, Using::BitBlt],
+        };
+        return auto_chain(
+            buffer,
+            chain,
+            |buffer, using| capture_stable(hwnd, buffer, using, area, crop_xy, crop_wh, exclude),
+            is_uniform,
+            |buffer| capture_window_from_screen(hwnd, buffer, area, crop_xy, crop_wh),
+        );
+    }
+
+    for _ in 0..RESIZE_RETRIES {
+        if let Some(size) = capture_once(hwnd, buffer, using, area, crop_xy, crop_wh, exclude)? {
+            return Ok(Some(size));
+        }
+    }
+    Ok(None)
+}
+
+// Runs the steps of Using::Auto: every method of chain in order until one
+// returns a frame that isn't blank, then fallback. A method that fails is
+// treated like one that returned a blank frame, so an error is only returned
+// if fallback fails too.
+pub(crate) fn auto_chain<E>(
+    buffer: &mut Vec<u8>,
+    chain: &[Using],
+    mut attempt: impl FnMut(&mut Vec<u8>, Using) -> Result<Option<WindowSize>, E>,
+    is_blank: impl Fn(&[u8]) -> bool,
+    fallback: impl FnOnce(&mut Vec<u8>) -> Result<WindowSize, E>,
+) -> Result<Option<WindowSize>, E> {
+    for &using in chain {
+        match attempt(buffer, using) {
+            Ok(Some(_)) if is_blank(buffer) => {}
+            Ok(size) => return Ok(size),
+            Err(_) => {
+                trace_event!(method = ?using, "capture failed, trying the next method");
+            }
+        }
+    }
+    trace_event!("window results are blank or failed, falling back to the screen");
+    fallback(buffer).map(Some)
+}
+
+// One capture attempt, None if the window changed size during the blit.
+// exclude is only clipped out for BitBlt, the other methods ignore the clip
+// region of the DC.
+fn capture_once(
+    hwnd: isize,
+    buffer: &mut Vec<u8>,
+    using: Using,
+    area: Area,
+    crop_xy: Option<[i32; 2]>,
+    crop_wh: Option<[i32; 2]>,
+    exclude: &[WindowRect],
+) -> Result<Option<WindowSize>, windows::core::Error> {
     buffer.clear();
+    let _span = trace_span!(
+        "capture_window",
+        hwnd,
+        method = ?using,
+        area = ?area,
+        width = tracing::field::Empty,
+        height = tracing::field::Empty,
+    );
     let hwnd = HWND(hwnd);
 
     unsafe {
-        #[allow(unused_must_use)]
-        {
-            SetProcessDpiAwareness(PROCESS_PER_MONITOR_DPI_AWARE);
-        }
+        set_dpi_awareness();
 
-        let hdc_screen = Hdc::get_dc(hwnd)?;
+        // see capture_rect
+        let hdc_screen = match using == Using::BitBlt && zero_client_rect(hwnd).is_some() {
+            true => Hdc::get_window_dc(hwnd)?,
+            false => Hdc::get_dc(hwnd)?,
+        };
+        trace_event!("GetDC");
 
-        // BitBlt support only ClientOnly
-        let rect = match (using, area) {
-            (Using::PrintWindow, Area::Full) => Rect::get_window_rect(hwnd),
-            (Using::BitBlt, _) | (Using::PrintWindow, Area::ClientOnly) => {
-                Rect::get_client_rect(hwnd)
-            }
-        }?;
+        let rect = capture_rect(hwnd, using, area)?;
+        trace_event!(width = rect.width, height = rect.height, "window rect");
 
         let [cx, cy] = crop_xy.unwrap_or([0, 0]);
         let [cw, ch] = crop_wh.unwrap_or([rect.width - cx, rect.height - cy]);
@@ -188,51 +847,76 @@ pub fn capture_window_into_bgr_buffer_ex(
         let hdc = CreatedHdc::create_compatible_dc(hdc_screen.hdc)?;
         let hbmp = match (crop, using) {
             (true, Using::BitBlt) => Hbitmap::create_compatible_bitmap(hdc_screen.hdc, cw, ch),
-            (false, Using::BitBlt) | (_, Using::PrintWindow) => {
-                Hbitmap::create_compatible_bitmap(hdc_screen.hdc, rect.width, rect.height)
-            }
+            _ => Hbitmap::create_compatible_bitmap(hdc_screen.hdc, rect.width, rect.height),
         }?;
+        let selected = SelectedObject::select(&hdc, &hbmp)?;
+        trace_event!("bitmap created");
 
-        if SelectObject(hdc.hdc, hbmp.hbitmap).is_invalid() {
-            return Err(windows::core::Error::from_win32());
-        }
-
-        let flags = PRINT_WINDOW_FLAGS(match area {
-            Area::Full => PW_RENDERFULLCONTENT,
-            Area::ClientOnly => PW_CLIENTONLY.0 | PW_RENDERFULLCONTENT,
-        });
+        let flags = print_window_flags(area);
 
         match using {
             Using::BitBlt => {
+                // the bitmap starts at the crop offset
+                for r in exclude {
+                    let (x, y) = (r.x - cx, r.y - cy);
+                    ExcludeClipRect(hdc.hdc, x, y, x + r.width, y + r.height);
+                }
                 if BitBlt(hdc.hdc, 0, 0, cw, ch, hdc_screen.hdc, cx, cy, SRCCOPY) == false {
                     return Err(windows::core::Error::from_win32());
                 }
+                trace_event!("BitBlt");
             }
-            Using::PrintWindow => {
+            Using::PrintWindow | Using::Auto => {
                 if PrintWindow(hwnd, hdc.hdc, flags) == false {
                     return Err(windows::core::Error::from_win32());
                 }
+                trace_event!("PrintWindow");
             }
+            Using::WmPrint => {
+                send_print_message(hwnd, HDC::from(&hdc), area);
+                trace_event!("WM_PRINT");
+            }
+        }
+
+        // a window that resized between measuring and blitting was drawn
+        // into a bitmap of the old size
+        let after = capture_rect(hwnd, using, area)?;
+        if after.width != rect.width || after.height != rect.height {
+            trace_event!("window resized during capture");
+            return Ok(None);
         }
 
+        // deselect before GetDIBits and before hbmp gets deleted,
+        // otherwise DeleteObject fails and the bitmap leaks
         let (width, height, hdc, hbmp) = match (crop, using) {
-            (true, Using::PrintWindow) => {
+            // PrintWindow can't render just a part of the window: with
+            // PW_RENDERFULLCONTENT the content is copied from the DWM
+            // redirection surface at the DC origin, ignoring the viewport
+            // origin and clip region set on the DC, so offsetting the origin
+            // or IntersectClipRect still draws (and needs) the whole window.
+            // The window is printed full size and the crop is cut out with a
+            // second, crop sized BitBlt. BitBlt doesn't need this, it reads
+            // the crop straight from the window DC.
+            (true, Using::PrintWindow | Using::Auto | Using::WmPrint) => {
                 let hdc2 = CreatedHdc::create_compatible_dc(hdc.hdc)?;
                 let hbmp2 = Hbitmap::create_compatible_bitmap(hdc.hdc, cw, ch)?;
-                let so = SelectObject(hdc2.hdc, hbmp2.hbitmap);
-                if so.is_invalid() {
-                    return Err(windows::core::Error::from_win32());
-                }
+                // restores hdc2 even if the BitBlt fails
+                let selected2 = SelectedObject::select(&hdc2, &hbmp2)?;
                 if BitBlt(hdc2.hdc, 0, 0, cw, ch, hdc.hdc, cx, cy, SRCCOPY) == false {
                     return Err(windows::core::Error::from_win32());
                 }
-                if SelectObject(hdc2.hdc, so).is_invalid() {
-                    return Err(windows::core::Error::from_win32());
-                }
+                drop(selected2);
+                drop(selected);
                 (cw, ch, hdc2, hbmp2)
             }
-            (true, Using::BitBlt) => (cw, ch, hdc, hbmp),
-            (false, _) => (rect.width, rect.height, hdc, hbmp),
+            (true, Using::BitBlt) => {
+                drop(selected);
+                (cw, ch, hdc, hbmp)
+            }
+            (false, _) => {
+                drop(selected);
+                (rect.width, rect.height, hdc, hbmp)
+            }
         };
 
         let bmih = BITMAPINFOHEADER {
@@ -261,62 +945,676 @@ pub fn capture_window_into_bgr_buffer_ex(
         if gdb == 0 || gdb == ERROR_INVALID_PARAMETER.0 as i32 {
             return Err(windows::core::Error::new(E_FAIL, "GetDIBits error".into()));
         }
+        buffer.set_len((4 * width * height) as usize);
+        trace_event!("GetDIBits");
+        trace_record!(_span, "width" = width, "height" = height);
+        Ok(Some(WindowSize {
+            width: width as u32,
+            height: height as u32,
+        }))
+    }
+}
+
+// Fallback of Using::Auto: raises the window to the top of the z-order
+// without activating it and copies its area from the screen DC, so it gets
+// whatever DWM composited, overlays included. Parts covered by topmost
+// windows or outside the screen are captured as they appear on screen.
+// The previous z-order position is restored afterwards.
+fn capture_window_from_screen(
+    hwnd: isize,
+    buffer: &mut Vec<u8>,
+    area: Area,
+    crop_xy: Option<[i32; 2]>,
+    crop_wh: Option<[i32; 2]>,
+) -> Result<WindowSize, windows::core::Error> {
+    let hwnd = HWND(hwnd);
+    unsafe {
+        set_dpi_awareness();
+
+        let window = Rect::get_window_rect(hwnd)?;
+        let rect = match area {
+            Area::Full => WindowRect {
+                x: window.left,
+                y: window.top,
+                width: window.width,
+                height: window.height,
+            },
+            Area::ClientOnly => {
+                let client = client_rect_in_window(hwnd)?;
+                WindowRect {
+                    x: window.left + client.x,
+                    y: window.top + client.y,
+                    ..client
+                }
+            }
+        };
+        let [cx, cy] = crop_xy.unwrap_or([0, 0]);
+        let [cw, ch] = crop_wh.unwrap_or([rect.width - cx, rect.height - cy]);
+
+        let hdc_screen = Hdc::get_dc(HWND::default())?;
+        let hdc = CreatedHdc::create_compatible_dc(hdc_screen.hdc)?;
+        let hbmp = Hbitmap::create_compatible_bitmap(hdc_screen.hdc, cw, ch)?;
+        let selected = SelectedObject::select(&hdc, &hbmp)?;
+        let (x, y) = (rect.x + cx, rect.y + cy);
+
+        // the window above this one, null if it is already on top
+        let above = GetWindow(hwnd, GW_HWNDPREV);
+        let flags = SWP_NOMOVE | SWP_NOSIZE | SWP_NOACTIVATE;
+        SetWindowPos(hwnd, HWND_TOP, 0, 0, 0, 0, flags);
+        // give DWM a frame to compose the raised window
+        thread::sleep(Duration::from_millis(50));
+
+        let blitted = if BitBlt(hdc.hdc, 0, 0, cw, ch, hdc_screen.hdc, x, y, SRCCOPY) == false {
+            Err(windows::core::Error::from_win32())
+        } else {
+            Ok(())
+        };
+
+        // put the window back where it was before reporting anything
+        if above.0 != 0 {
+            SetWindowPos(hwnd, above, 0, 0, 0, 0, flags);
+        }
+        blitted?;
+        trace_event!("BitBlt from screen");
+        drop(selected);
+
+        get_dib_bits_into(hdc.hdc, hbmp.hbitmap, cw, ch, buffer)
+            .map_err(|_| windows::core::Error::new(E_FAIL, "GetDIBits error".into()))?;
         Ok(WindowSize {
+            width: cw as u32,
+            height: ch as u32,
+        })
+    }
+}
+
+// size of the bitmap a capture with using and area needs
+pub(crate) fn capture_rect(
+    hwnd: HWND,
+    using: Using,
+    area: Area,
+) -> Result<Rect, windows::core::Error> {
+    // BitBlt support only ClientOnly, except for windows without a client
+    // area (some controls), those are blitted whole from their window DC
+    match (using, area) {
+        (Using::BitBlt, _) if zero_client_rect(hwnd).is_some() => Rect::get_window_rect(hwnd),
+        (Using::BitBlt, _) | (_, Area::ClientOnly) => Rect::get_client_rect(hwnd),
+        (_, Area::Full) => Rect::get_window_rect(hwnd),
+    }
+}
+
+// The window rect of a window that has one but reports an empty client
+// rect, None for every other window
+pub(crate) fn zero_client_rect(hwnd: HWND) -> Option<WindowRect> {
+    if Rect::get_client_rect(hwnd).is_ok() {
+        return None;
+    }
+    Rect::get_window_rect(hwnd).ok().map(WindowRect::from)
+}
+
+// the window paints synchronously, there is no meaningful return value
+pub(crate) unsafe fn send_print_message(hwnd: HWND, hdc: HDC, area: Area) {
+    let (message, flags) = match area {
+        Area::Full => (
+            WM_PRINT,
+            PRF_NONCLIENT | PRF_CLIENT | PRF_CHILDREN | PRF_ERASEBKGND,
+        ),
+        Area::ClientOnly => (WM_PRINTCLIENT, PRF_CLIENT | PRF_CHILDREN | PRF_ERASEBKGND),
+    };
+    SendMessageW(
+        hwnd,
+        message,
+        WPARAM(hdc.0 as usize),
+        LPARAM(flags as isize),
+    );
+}
+
+pub(crate) fn print_window_flags(area: Area) -> PRINT_WINDOW_FLAGS {
+    PRINT_WINDOW_FLAGS(match area {
+        Area::Full => PW_RENDERFULLCONTENT,
+        Area::ClientOnly => PW_CLIENTONLY.0 | PW_RENDERFULLCONTENT,
+    })
+}
+
+// Lets the window paint itself at width x height instead of scaling a full
+// size capture: the memory DC maps the window extent onto the target size
+// before PrintWindow. PW_RENDERFULLCONTENT copies the DWM surface and would
+// ignore the mapping, so it isn't used and windows drawn by the GPU may
+// come back black. Windows that paint with device coordinates ignore the
+// scaling, the result is then a cut off full size image.
+pub fn capture_window_render_scaled(
+    hwnd: isize,
+    width: i32,
+    height: i32,
+) -> Result<RgbBuf, WSError> {
+    // a zero extent makes the mapping degenerate instead of failing
+    if width <= 0 || height <= 0 {
+        return Err(WSError::InvalidSize);
+    }
+    check_window(hwnd)?;
+    let hwnd = HWND(hwnd);
+
+    unsafe {
+        set_dpi_awareness();
+
+        let hdc_screen = Hdc::get_dc(hwnd)?;
+        let rect = Rect::get_window_rect(hwnd)?;
+
+        let hdc = CreatedHdc::create_compatible_dc(hdc_screen.hdc)?;
+        let hbmp = Hbitmap::create_compatible_bitmap(hdc_screen.hdc, width, height)?;
+        let selected = SelectedObject::select(&hdc, &hbmp)?;
+
+        SetMapMode(hdc.hdc, MM_ANISOTROPIC);
+        SetWindowExtEx(hdc.hdc, rect.width, rect.height, None);
+        SetViewportExtEx(hdc.hdc, width, height, None);
+        if PrintWindow(hwnd, hdc.hdc, PRINT_WINDOW_FLAGS(0)) == false {
+            return Err(windows::core::Error::from_win32().into());
+        }
+        drop(selected);
+
+        let mut pixels = get_dib_bits(hdc.hdc, hbmp.hbitmap, width, height)?;
+        pixels.chunks_exact_mut(4).for_each(|c| c.swap(0, 2));
+
+        Ok(RgbBuf {
+            pixels,
             width: width as u32,
             height: height as u32,
         })
     }
 }
 
-pub fn capture_display() -> Result<RgbBuf, WSError> {
+// Scales the window into a max_w x max_h box keeping its aspect ratio,
+// windows smaller than the box are not enlarged. GDI does the HALFTONE
+// scaling, which is much faster than capturing full size and resizing.
+pub fn capture_window_thumbnail_sized(
+    hwnd: isize,
+    max_w: u32,
+    max_h: u32,
+) -> Result<RgbBuf, WSError> {
+    check_window(hwnd)?;
+    let hwnd = HWND(hwnd);
+
     unsafe {
-        // win 8.1 temporary DPI aware
-        #[allow(unused_must_use)]
-        {
-            SetProcessDpiAwareness(PROCESS_PER_MONITOR_DPI_AWARE);
+        set_dpi_awareness();
+
+        let hdc_screen = Hdc::get_dc(hwnd)?;
+        let rect = Rect::get_window_rect(hwnd)?;
+
+        let hdc = CreatedHdc::create_compatible_dc(hdc_screen.hdc)?;
+        let hbmp = Hbitmap::create_compatible_bitmap(hdc_screen.hdc, rect.width, rect.height)?;
+        let _selected = SelectedObject::select(&hdc, &hbmp)?;
+        if PrintWindow(hwnd, hdc.hdc, PRINT_WINDOW_FLAGS(PW_RENDERFULLCONTENT)) == false {
+            return Err(windows::core::Error::from_win32().into());
         }
-        // for win 10
-        //SetThreadDpiAwarenessContext(DPI_AWARENESS_CONTEXT_PER_MONITOR_AWARE_V2);
-        let hdc_screen = GetDC(HWND::default());
-        if hdc_screen.is_invalid() {
-            return Err(WSError::GetDCIsNull);
+
+        let (width, height) = fit_size(rect.width, rect.height, max_w, max_h);
+
+        let hdc_thumb = CreatedHdc::create_compatible_dc(hdc.hdc)?;
+        let hbmp_thumb = Hbitmap::create_compatible_bitmap(hdc_screen.hdc, width, height)?;
+        let selected_thumb = SelectedObject::select(&hdc_thumb, &hbmp_thumb)?;
+
+        set_stretch_quality(HDC::from(&hdc_thumb), StretchQuality::Smooth);
+        if StretchBlt(
+            hdc_thumb.hdc,
+            0,
+            0,
+            width,
+            height,
+            hdc.hdc,
+            0,
+            0,
+            rect.width,
+            rect.height,
+            SRCCOPY,
+        ) == false
+        {
+            return Err(WSError::StretchBltIsZero);
         }
+        drop(selected_thumb);
+
+        let mut pixels = get_dib_bits(hdc_thumb.hdc, hbmp_thumb.hbitmap, width, height)?;
+        pixels.chunks_exact_mut(4).for_each(|c| c.swap(0, 2));
 
-        let hdc = CreateCompatibleDC(hdc_screen);
-        if hdc.is_invalid() {
-            ReleaseDC(HWND::default(), hdc_screen);
-            return Err(WSError::CreateCompatibleDCIsNull);
+        Ok(RgbBuf {
+            pixels,
+            width: width as u32,
+            height: height as u32,
+        })
+    }
+}
+
+// Captures rect given in virtual screen coordinates. The rect is clipped to
+// the virtual screen, clipped is set if that cut anything off.
+pub fn capture_region(rect: WindowRect) -> Result<RegionCapture, WSError> {
+    if is_secure_desktop_active() {
+        return Err(WSError::SecureDesktopActive);
+    }
+    let visible = rect
+        .intersect(&virtual_screen_bounds())
+        .ok_or(WSError::RegionOutOfBounds)?;
+
+    unsafe {
+        set_dpi_awareness();
+
+        let hdc_screen = Hdc::get_dc(HWND::default())?;
+        let hdc = CreatedHdc::create_compatible_dc(hdc_screen.hdc)?;
+        let hbmp =
+            Hbitmap::create_compatible_bitmap(hdc_screen.hdc, visible.width, visible.height)?;
+        let selected = SelectedObject::select(&hdc, &hbmp)?;
+
+        if BitBlt(
+            hdc.hdc,
+            0,
+            0,
+            visible.width,
+            visible.height,
+            hdc_screen.hdc,
+            visible.x,
+            visible.y,
+            SRCCOPY,
+        ) == false
+        {
+            return Err(WSError::BitBltError);
         }
+        drop(selected);
+
+        let mut pixels = get_dib_bits(hdc.hdc, hbmp.hbitmap, visible.width, visible.height)?;
+        pixels.chunks_exact_mut(4).for_each(|c| c.swap(0, 2));
 
-        let x = GetSystemMetrics(SM_XVIRTUALSCREEN);
-        let y = GetSystemMetrics(SM_YVIRTUALSCREEN);
-        let width = GetSystemMetrics(SM_CXVIRTUALSCREEN);
-        let height = GetSystemMetrics(SM_CYVIRTUALSCREEN);
+        Ok(RegionCapture {
+            buf: RgbBuf {
+                pixels,
+                width: visible.width as u32,
+                height: visible.height as u32,
+            },
+            rect: visible,
+            clipped: visible != rect,
+        })
+    }
+}
 
-        let hbmp = CreateCompatibleBitmap(hdc_screen, width, height);
-        if hbmp.is_invalid() {
-            DeleteDC(hdc);
-            ReleaseDC(HWND::default(), hdc_screen);
-            return Err(WSError::CreateCompatibleBitmapIsNull);
+// Same box as capture_window_thumbnail_sized, but the window is captured at
+// full size and averaged down in Rust. Slower, no aliasing.
+pub fn capture_window_thumbnail_hq(hwnd: isize, w: u32, h: u32) -> Result<RgbBuf, WSError> {
+    let buf = capture_window(hwnd)?;
+    let (width, height) = fit_size(buf.width as i32, buf.height as i32, w, h);
+    Ok(buf.resize(width as u32, height as u32))
+}
+
+pub(crate) unsafe fn set_stretch_quality(hdc: HDC, quality: StretchQuality) {
+    match quality {
+        StretchQuality::Fast => {
+            SetStretchBltMode(hdc, COLORONCOLOR);
+        }
+        StretchQuality::Smooth => {
+            // HALFTONE requires the brush origin to be reset afterwards
+            SetStretchBltMode(hdc, HALFTONE);
+            SetBrushOrgEx(hdc, 0, 0, None);
         }
+    }
+}
 
-        let so = SelectObject(hdc, hbmp);
-        if so.is_invalid() {
-            DeleteDC(hdc);
-            DeleteObject(hbmp);
-            ReleaseDC(HWND::default(), hdc_screen);
-            return Err(WSError::SelectObjectError);
+// Copies rect (virtual screen coordinates) from the screen scaled into a
+// max_w x max_h box keeping the aspect ratio, never enlarged
+pub(crate) fn capture_screen_scaled(
+    rect: WindowRect,
+    max_w: u32,
+    max_h: u32,
+    quality: StretchQuality,
+) -> Result<RgbBuf, WSError> {
+    if is_secure_desktop_active() {
+        return Err(WSError::SecureDesktopActive);
+    }
+    set_dpi_awareness();
+    let (width, height) = fit_size(rect.width, rect.height, max_w, max_h);
+    unsafe {
+        let hdc_screen = Hdc::get_dc(HWND::default()).map_err(|_| WSError::GetDCIsNull)?;
+        let hdc = CreatedHdc::create_compatible_dc(hdc_screen.hdc)
+            .map_err(|_| WSError::CreateCompatibleDCIsNull)?;
+        let hbmp = Hbitmap::create_compatible_bitmap(hdc_screen.hdc, width, height)
+            .map_err(|_| WSError::CreateCompatibleBitmapIsNull)?;
+        let selected =
+            SelectedObject::select(&hdc, &hbmp).map_err(|_| WSError::SelectObjectError)?;
+
+        set_stretch_quality(HDC::from(&hdc), quality);
+        let sb = StretchBlt(
+            hdc.hdc,
+            0,
+            0,
+            width,
+            height,
+            hdc_screen.hdc,
+            rect.x,
+            rect.y,
+            rect.width,
+            rect.height,
+            SRCCOPY,
+        );
+        if sb == false {
+            return Err(WSError::StretchBltIsZero);
         }
+        drop(selected);
+
+        let mut pixels = get_dib_bits(hdc.hdc, hbmp.hbitmap, width, height)?;
+        pixels.chunks_exact_mut(4).for_each(|c| c.swap(0, 2));
+        Ok(RgbBuf {
+            pixels,
+            width: width as u32,
+            height: height as u32,
+        })
+    }
+}
+
+// whole virtual screen scaled into a max_w x max_h box
+pub fn capture_display_scaled(
+    max_w: u32,
+    max_h: u32,
+    quality: StretchQuality,
+) -> Result<RgbBuf, WSError> {
+    capture_screen_scaled(virtual_screen_bounds(), max_w, max_h, quality)
+}
+
+// largest size with the aspect ratio of width x height that fits in
+// max_w x max_h, never larger than the original
+pub(crate) fn fit_size(width: i32, height: i32, max_w: u32, max_h: u32) -> (i32, i32) {
+    let scale = f64::min(max_w as f64 / width as f64, max_h as f64 / height as f64).min(1.0);
+    (
+        ((width as f64 * scale).round() as i32).max(1),
+        ((height as f64 * scale).round() as i32).max(1),
+    )
+}
+
+// color of a single screen pixel, x and y are virtual screen coordinates
+pub fn get_pixel_color(x: i32, y: i32) -> Result<[u8; 3], WSError> {
+    set_dpi_awareness();
+    let hdc_screen = Hdc::get_dc(HWND::default())?;
+    // COLORREF is 0x00BBGGRR, CLR_INVALID when the pixel is outside the
+    // clipping region
+    match unsafe { GetPixel(hdc_screen.hdc, x, y) }.0 {
+        0xFFFF_FFFF => Err(WSError::GetPixelError),
+        c => Ok([c as u8, (c >> 8) as u8, (c >> 16) as u8]),
+    }
+}
+
+// reads a 32 bit top-down copy of hbitmap, channels are in BGRA order
+unsafe fn get_dib_bits<P0>(
+    hdc: P0,
+    hbitmap: HBITMAP,
+    width: i32,
+    height: i32,
+) -> Result<Vec<u8>, WSError>
+where
+    P0: IntoParam<HDC>,
+{
+    let mut buf = Vec::new();
+    get_dib_bits_into(hdc, hbitmap, width, height, &mut buf)?;
+    Ok(buf)
+}
+
+// same as get_dib_bits, reusing the allocation of buffer
+unsafe fn get_dib_bits_into<P0>(
+    hdc: P0,
+    hbitmap: HBITMAP,
+    width: i32,
+    height: i32,
+    buffer: &mut Vec<u8>,
+) -> Result<(), WSError>
+where
+    P0: IntoParam<HDC>,
+{
+    let bmih = BITMAPINFOHEADER {
+        biSize: size_of::<BITMAPINFOHEADER>() as u32,
+        biPlanes: 1,
+        biBitCount: 32,
+        biWidth: width,
+        biHeight: -height,
+        biCompression: BI_RGB.0 as u32,
+        ..Default::default()
+    };
+    let mut bmi = BITMAPINFO {
+        bmiHeader: bmih,
+        ..Default::default()
+    };
+
+    let len = (4 * width * height) as usize;
+    buffer.clear();
+    buffer.reserve(len);
+
+    let gdb = GetDIBits(
+        hdc,
+        hbitmap,
+        0,
+        height as u32,
+        Some(buffer.as_mut_ptr() as *mut core::ffi::c_void),
+        &mut bmi,
+        DIB_RGB_COLORS,
+    );
+    if gdb == 0 || gdb == ERROR_INVALID_PARAMETER.0 as i32 {
+        return Err(WSError::GetDIBitsError);
+    }
+    // GetDIBits wrote all rows of the 32 bit bitmap
+    buffer.set_len(len);
+    Ok(())
+}
+
+// Windows excluded with WDA_EXCLUDEFROMCAPTURE, including the capturing
+// app's own, are not in display captures, the OS composes the frame
+// without them. See the display_affinity test.
+pub fn capture_display() -> Result<RgbBuf, WSError> {
+    Ok(capture_display_with_origin()?.0)
+}
+
+// The origin maps screen coordinates into the returned buffer
+pub fn capture_display_with_origin() -> Result<(RgbBuf, VirtualScreenOrigin), WSError> {
+    let mut pixels = Vec::new();
+    let capture = capture_display_into_buffer_ex(&mut pixels)?;
+    Ok((
+        RgbBuf {
+            pixels,
+            width: capture.width,
+            height: capture.height,
+        },
+        capture.origin,
+    ))
+}
+
+// Fills buffer with the RGBA pixels of the whole virtual screen, reusing its
+// allocation between calls
+pub fn capture_display_into_buffer_ex(buffer: &mut Vec<u8>) -> Result<DisplayCapture, WSError> {
+    if is_secure_desktop_active() {
+        return Err(WSError::SecureDesktopActive);
+    }
+    let _span = trace_span!(
+        "capture_display",
+        method = "StretchBlt",
+        width = tracing::field::Empty,
+        height = tracing::field::Empty,
+    );
+    unsafe {
+        set_dpi_awareness();
+
+        let hdc_screen = Hdc::get_dc(HWND::default()).map_err(|_| WSError::GetDCIsNull)?;
+        let hdc = CreatedHdc::create_compatible_dc(hdc_screen.hdc)
+            .map_err(|_| WSError::CreateCompatibleDCIsNull)?;
+        trace_event!("GetDC");
+
+        let WindowRect {
+            x,
+            y,
+            width,
+            height,
+        } = virtual_screen_bounds();
+
+        let hbmp = Hbitmap::create_compatible_bitmap(hdc_screen.hdc, width, height)
+            .map_err(|_| WSError::CreateCompatibleBitmapIsNull)?;
+        let selected =
+            SelectedObject::select(&hdc, &hbmp).map_err(|_| WSError::SelectObjectError)?;
 
         let sb = StretchBlt(
-            hdc, 0, 0, width, height, hdc_screen, x, y, width, height, SRCCOPY,
+            hdc.hdc,
+            0,
+            0,
+            width,
+            height,
+            hdc_screen.hdc,
+            x,
+            y,
+            width,
+            height,
+            SRCCOPY,
         );
         if sb == false {
-            DeleteDC(hdc);
-            DeleteObject(hbmp);
-            ReleaseDC(HWND::default(), hdc_screen);
             return Err(WSError::StretchBltIsZero);
         }
+        drop(selected);
+        trace_event!("StretchBlt");
+
+        get_dib_bits_into(hdc.hdc, hbmp.hbitmap, width, height, buffer)?;
+        buffer.chunks_exact_mut(4).for_each(|c| c.swap(0, 2));
+        trace_event!("GetDIBits");
+        trace_record!(_span, "width" = width, "height" = height);
+
+        Ok(DisplayCapture {
+            width: width as u32,
+            height: height as u32,
+            origin: VirtualScreenOrigin { x, y },
+        })
+    }
+}
+
+// Runs frames back to back display captures into one reused buffer, so the
+// numbers show steady state capture cost rather than allocation. One warm up
+// capture before the measurement sizes the buffer.
+pub fn capture_display_bench(frames: u32) -> Result<CaptureBench, WSError> {
+    let frames = frames.max(1);
+    let mut buffer = Vec::new();
+    capture_display_into_buffer_ex(&mut buffer)?;
+
+    let start = Instant::now();
+    let mut bytes = 0u64;
+    for _ in 0..frames {
+        capture_display_into_buffer_ex(&mut buffer)?;
+        bytes += buffer.len() as u64;
+    }
+    let elapsed = start.elapsed();
+
+    let secs = elapsed.as_secs_f64().max(f64::EPSILON);
+    Ok(CaptureBench {
+        frames,
+        fps: frames as f64 / secs,
+        avg_latency: elapsed / frames,
+        mb_per_sec: bytes as f64 / 1_000_000.0 / secs,
+    })
+}
+
+// Captures the display and returns the spans of rows that differ from prev
+// as (start_row, end_row), end exclusive, adjacent changed rows merged.
+// Fails with SizeMismatch if the display size no longer matches prev.
+pub fn capture_display_dirty(prev: &RgbBuf) -> Result<(RgbBuf, Vec<(u32, u32)>), WSError> {
+    let frame = capture_display()?;
+    if frame.width != prev.width || frame.height != prev.height {
+        return Err(WSError::SizeMismatch);
+    }
+    let row_len = frame.width as usize * 4;
+    if row_len == 0 {
+        return Ok((frame, Vec::new()));
+    }
+    let rows = frame
+        .pixels
+        .chunks_exact(row_len)
+        .zip(prev.pixels.chunks_exact(row_len));
+    let changed = rows
+        .enumerate()
+        .filter(|(_, (a, b))| a != b)
+        .map(|(y, _)| (y as u32, y as u32 + 1));
+    let spans = merge_spans(changed);
+    Ok((frame, spans))
+}
+
+// Sorts (start, end) spans, end exclusive, and merges the ones that overlap
+// or touch
+pub(crate) fn merge_spans(spans: impl IntoIterator<Item = (u32, u32)>) -> Vec<(u32, u32)> {
+    let mut spans: Vec<(u32, u32)> = spans.into_iter().filter(|(s, e)| s < e).collect();
+    spans.sort_unstable();
+    let mut merged: Vec<(u32, u32)> = Vec::with_capacity(spans.len());
+    for (start, end) in spans {
+        match merged.last_mut() {
+            Some((_, last)) if *last >= start => *last = (*last).max(end),
+            _ => merged.push((start, end)),
+        }
+    }
+    merged
+}
+
+// GetClassLongPtrW only exists on 64-bit targets, on 32-bit the class long
+// is pointer sized already
+#[cfg(target_pointer_width = "64")]
+unsafe fn get_class_icon(hwnd: HWND, index: GET_CLASS_LONG_INDEX) -> HICON {
+    HICON(GetClassLongPtrW(hwnd, index) as isize)
+}
+
+#[cfg(target_pointer_width = "32")]
+unsafe fn get_class_icon(hwnd: HWND, index: GET_CLASS_LONG_INDEX) -> HICON {
+    HICON(GetClassLongW(hwnd, index) as isize)
+}
+
+pub fn get_window_icon(hwnd: isize, large: bool) -> Result<RgbBuf, WSError> {
+    check_window(hwnd)?;
+    let hwnd = HWND(hwnd);
+
+    unsafe {
+        let (icon_type, class_index) = match large {
+            true => (ICON_BIG, GCLP_HICON),
+            false => (ICON_SMALL2, GCLP_HICONSM),
+        };
+
+        // icon set by the window itself takes precedence over the class icon,
+        // a hung window doesn't answer WM_GETICON and gets the class icon
+        let mut icon = 0;
+        SendMessageTimeoutW(
+            hwnd,
+            WM_GETICON,
+            WPARAM(icon_type as usize),
+            LPARAM(0),
+            SMTO_ABORTIFHUNG,
+            500,
+            Some(&mut icon),
+        );
+        let mut hicon = HICON(icon as isize);
+        if hicon.is_invalid() {
+            hicon = get_class_icon(hwnd, class_index);
+        }
+        if hicon.is_invalid() {
+            return Err(WSError::NoIcon);
+        }
+
+        let mut info = ICONINFO::default();
+        if GetIconInfo(hicon, &mut info) == false {
+            return Err(windows::core::Error::from_win32().into());
+        }
+        // GetIconInfo hands out copies of both bitmaps, wrap them so they get deleted
+        let mask = Hbitmap {
+            hbitmap: info.hbmMask,
+        };
+        let color = Hbitmap {
+            hbitmap: info.hbmColor,
+        };
+        // monochrome icons have no color bitmap
+        if color.hbitmap.is_invalid() {
+            return Err(WSError::NoIcon);
+        }
+
+        let mut bitmap = BITMAP::default();
+        if GetObjectW(
+            color.hbitmap,
+            size_of::<BITMAP>() as i32,
+            Some(&mut bitmap as *mut BITMAP as *mut core::ffi::c_void),
+        ) == 0
+        {
+            return Err(WSError::GetDIBitsError);
+        }
+        let (width, height) = (bitmap.bmWidth, bitmap.bmHeight);
+
+        let hdc_screen = Hdc::get_dc(HWND::default())?;
 
         let bmih = BITMAPINFOHEADER {
             biSize: size_of::<BITMAPINFOHEADER>() as u32,
@@ -327,7 +1625,6 @@ pub fn capture_display() -> Result<RgbBuf, WSError> {
             biCompression: BI_RGB.0 as u32,
             ..Default::default()
         };
-
         let mut bmi = BITMAPINFO {
             bmiHeader: bmih,
             ..Default::default()
@@ -336,8 +1633,8 @@ pub fn capture_display() -> Result<RgbBuf, WSError> {
         let mut buf: Vec<u8> = vec![0; (4 * width * height) as usize];
 
         let gdb = GetDIBits(
-            hdc,
-            hbmp,
+            hdc_screen.hdc,
+            color.hbitmap,
             0,
             height as u32,
             Some(buf.as_mut_ptr() as *mut core::ffi::c_void),
@@ -345,17 +1642,19 @@ pub fn capture_display() -> Result<RgbBuf, WSError> {
             DIB_RGB_COLORS,
         );
         if gdb == 0 || gdb == ERROR_INVALID_PARAMETER.0 as i32 {
-            DeleteDC(hdc);
-            DeleteObject(hbmp);
-            ReleaseDC(HWND::default(), hdc_screen);
             return Err(WSError::GetDIBitsError);
         }
 
-        buf.chunks_exact_mut(4).for_each(|c| c.swap(0, 2));
+        // legacy icons have no alpha channel, their transparency is in the
+        // AND mask: black (opaque) or white (transparent) read as 32 bpp
+        if buf.chunks_exact(4).all(|p| p[3] == 0) {
+            let mask = get_dib_bits(hdc_screen.hdc, mask.hbitmap, width, height)?;
+            for (p, m) in buf.chunks_exact_mut(4).zip(mask.chunks_exact(4)) {
+                p[3] = if m[..3] == [0, 0, 0] { 255 } else { 0 };
+            }
+        }
 
-        DeleteDC(hdc);
-        DeleteObject(hbmp);
-        ReleaseDC(HWND::default(), hdc_screen);
+        buf.chunks_exact_mut(4).for_each(|c| c.swap(0, 2));
 
         Ok(RgbBuf {
             pixels: buf,
@@ -0,0 +1,37 @@
+use std::sync::mpsc::{sync_channel, Receiver, TrySendError};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crate::capture::{capture_window, RgbBuf, WSError};
+
+// frames waiting for the consumer, anything captured beyond that is dropped
+const CHANNEL_CAPACITY: usize = 2;
+
+// Captures hwnd at fps on a background thread. When the consumer falls behind
+// new frames are dropped instead of stalling the capture. The thread stops
+// when the receiver is dropped, or with the error of the first failed capture.
+pub fn spawn_capture_channel(
+    hwnd: isize,
+    fps: u32,
+) -> (JoinHandle<Result<(), WSError>>, Receiver<RgbBuf>) {
+    let (tx, rx) = sync_channel(CHANNEL_CAPACITY);
+    let interval = Duration::from_secs(1) / fps.max(1);
+
+    let handle = thread::spawn(move || {
+        let mut next = Instant::now();
+        loop {
+            match tx.try_send(capture_window(hwnd)?) {
+                Ok(()) | Err(TrySendError::Full(_)) => {}
+                Err(TrySendError::Disconnected(_)) => return Ok(()),
+            }
+            next += interval;
+            match next.checked_duration_since(Instant::now()) {
+                Some(wait) => thread::sleep(wait),
+                // running late, don't try to catch up with a burst of frames
+                None => next = Instant::now(),
+            }
+        }
+    });
+
+    (handle, rx)
+}
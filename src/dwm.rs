@@ -0,0 +1,115 @@
+use windows::core::{w, PCWSTR};
+use windows::Win32::Foundation::{HWND, RECT};
+use windows::Win32::Graphics::Dwm::{
+    DwmFlush, DwmIsCompositionEnabled, DwmQueryThumbnailSourceSize, DwmRegisterThumbnail,
+    DwmUnregisterThumbnail, DwmUpdateThumbnailProperties, DWM_THUMBNAIL_PROPERTIES,
+    DWM_TNP_OPACITY, DWM_TNP_RECTDESTINATION, DWM_TNP_VISIBLE,
+};
+use windows::Win32::UI::WindowsAndMessaging::{
+    CreateWindowExW, DestroyWindow, SetWindowPos, HWND_TOPMOST, SWP_NOACTIVATE, SWP_SHOWWINDOW,
+    WS_EX_NOACTIVATE, WS_EX_TOOLWINDOW, WS_EX_TOPMOST, WS_POPUP,
+};
+
+use crate::capture::{capture_region, check_window, fit_size, RgbBuf, WSError};
+use crate::geometry::WindowRect;
+use crate::utils::set_dpi_awareness;
+
+// borderless popup the thumbnail is rendered into, destroyed on drop
+struct HostWindow(HWND);
+
+impl Drop for HostWindow {
+    fn drop(&mut self) {
+        unsafe {
+            DestroyWindow(self.0);
+        }
+    }
+}
+
+struct Thumbnail(isize);
+
+impl Drop for Thumbnail {
+    fn drop(&mut self) {
+        unsafe {
+            let _ = DwmUnregisterThumbnail(self.0);
+        }
+    }
+}
+
+// Renders a live DWM thumbnail of the window into a temporary topmost host
+// window and captures that from the screen. Unlike PrintWindow this works
+// for minimized (last frame before minimizing) and GPU-accelerated windows.
+// The host is shown at (0, 0) of the virtual screen, which is the top left
+// of the primary monitor, for about one frame, so it is briefly visible on
+// screen there. A thumbnail reaching past the virtual screen from there
+// comes back clipped. Needs DWM composition, which is always on since
+// Windows 8.
+pub fn capture_window_dwm(hwnd: isize, max_w: u32, max_h: u32) -> Result<RgbBuf, WSError> {
+    check_window(hwnd)?;
+    set_dpi_awareness();
+    unsafe {
+        if !DwmIsCompositionEnabled()?.as_bool() {
+            return Err(WSError::DwmCompositionDisabled);
+        }
+
+        // the Static system class saves registering a window class
+        let host = CreateWindowExW(
+            WS_EX_TOOLWINDOW | WS_EX_NOACTIVATE | WS_EX_TOPMOST,
+            w!("Static"),
+            PCWSTR::null(),
+            WS_POPUP,
+            0,
+            0,
+            1,
+            1,
+            None,
+            None,
+            None,
+            None,
+        );
+        if host == HWND(0) {
+            return Err(windows::core::Error::from_win32().into());
+        }
+        let host = HostWindow(host);
+
+        let thumbnail = Thumbnail(DwmRegisterThumbnail(host.0, HWND(hwnd))?);
+        let source = DwmQueryThumbnailSourceSize(thumbnail.0)?;
+        if source.cx <= 0 || source.cy <= 0 {
+            return Err(WSError::ThumbnailEmpty);
+        }
+        let (width, height) = fit_size(source.cx, source.cy, max_w, max_h);
+
+        let properties = DWM_THUMBNAIL_PROPERTIES {
+            dwFlags: DWM_TNP_RECTDESTINATION | DWM_TNP_VISIBLE | DWM_TNP_OPACITY,
+            rcDestination: RECT {
+                left: 0,
+                top: 0,
+                right: width,
+                bottom: height,
+            },
+            opacity: 255,
+            fVisible: true.into(),
+            ..Default::default()
+        };
+        DwmUpdateThumbnailProperties(thumbnail.0, &properties)?;
+
+        SetWindowPos(
+            host.0,
+            HWND_TOPMOST,
+            0,
+            0,
+            width,
+            height,
+            SWP_NOACTIVATE | SWP_SHOWWINDOW,
+        );
+        // wait until the compositor has presented a frame with the thumbnail
+        DwmFlush()?;
+
+        let capture = capture_region(WindowRect {
+            x: 0,
+            y: 0,
+            width,
+            height,
+        })?;
+        Ok(capture.buf)
+    }
+}
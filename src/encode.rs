@@ -0,0 +1,108 @@
+#[cfg(feature = "image")]
+use std::io::Write;
+
+#[cfg(any(feature = "webp", feature = "image"))]
+use crate::capture::{RgbBuf, WSError};
+
+// result of RgbBuf::to_jpeg_under_size
+#[cfg(feature = "image")]
+#[derive(Debug)]
+pub struct SizedJpeg {
+    pub bytes: Vec<u8>,
+    pub quality: u8,
+    // even quality 1 didn't fit, bytes is that encode
+    pub over_budget: bool,
+}
+
+// The encoders drop the alpha channel: GDI leaves it undefined (often 0) for
+// most windows, which would make the image come out transparent.
+#[cfg(any(feature = "webp", feature = "image"))]
+impl RgbBuf {
+    fn rgb_bytes(&self) -> Vec<u8> {
+        self.pixels
+            .chunks_exact(4)
+            .flat_map(|p| [p[0], p[1], p[2]])
+            .collect()
+    }
+}
+
+#[cfg(feature = "webp")]
+impl RgbBuf {
+    // quality 0.0..100.0 encodes lossy, 100.0 and above lossless
+    pub fn to_webp_bytes(&self, quality: f32) -> Result<Vec<u8>, WSError> {
+        let rgb = self.rgb_bytes();
+        let lossless = quality >= 100.0;
+        let encoded = webp::Encoder::from_rgb(&rgb, self.width, self.height)
+            .encode_simple(lossless, quality.clamp(0.0, 100.0))
+            .map_err(|_| WSError::EncodeError)?;
+        Ok(encoded.to_vec())
+    }
+}
+
+// Both encode straight into w, e.g. a socket or response body, without
+// building the file in memory first. The buffer is only read.
+#[cfg(feature = "image")]
+impl RgbBuf {
+    pub fn write_png<W: Write>(&self, w: &mut W) -> Result<(), WSError> {
+        use image::ImageEncoder;
+        image::codecs::png::PngEncoder::new(w)
+            .write_image(
+                &self.rgb_bytes(),
+                self.width,
+                self.height,
+                image::ColorType::Rgb8,
+            )
+            .map_err(|_| WSError::EncodeError)
+    }
+
+    // quality 1..=100
+    pub fn write_jpeg<W: Write>(&self, w: &mut W, quality: u8) -> Result<(), WSError> {
+        image::codecs::jpeg::JpegEncoder::new_with_quality(w, quality.clamp(1, 100))
+            .encode(
+                &self.rgb_bytes(),
+                self.width,
+                self.height,
+                image::ColorType::Rgb8,
+            )
+            .map_err(|_| WSError::EncodeError)
+    }
+    // Binary searches the highest JPEG quality whose encode is at most
+    // max_bytes long, about 7 encodes. If even quality 1 is too large that
+    // encode is returned with over_budget set.
+    pub fn to_jpeg_under_size(&self, max_bytes: usize) -> Result<SizedJpeg, WSError> {
+        let rgb = self.rgb_bytes();
+        let encode = |quality: u8| {
+            let mut bytes = Vec::new();
+            image::codecs::jpeg::JpegEncoder::new_with_quality(&mut bytes, quality)
+                .encode(&rgb, self.width, self.height, image::ColorType::Rgb8)
+                .map_err(|_| WSError::EncodeError)?;
+            Ok::<_, WSError>(bytes)
+        };
+
+        let mut best = None;
+        let (mut lo, mut hi) = (1u8, 100u8);
+        while lo <= hi {
+            let quality = lo + (hi - lo) / 2;
+            let bytes = encode(quality)?;
+            if bytes.len() <= max_bytes {
+                best = Some((bytes, quality));
+                lo = quality + 1;
+            } else {
+                hi = quality - 1;
+            }
+        }
+
+        Ok(match best {
+            Some((bytes, quality)) => SizedJpeg {
+                bytes,
+                quality,
+                over_budget: false,
+            },
+            None => SizedJpeg {
+                bytes: encode(1)?,
+                quality: 1,
+                over_budget: true,
+            },
+        })
+    }
+}
@@ -0,0 +1,7 @@
+// RAII wrappers around the GDI handles the capture functions are built from,
+// for composing capture flows the crate doesn't provide. Every constructor
+// returns a Result and every handle is released on drop. Declare a
+// SelectedObject after the Hbitmap it selects so it is dropped first, the
+// borrow checker rejects anything else. into_raw hands a handle over without
+// releasing it.
+pub use crate::wrappers::{CreatedHdc, Hbitmap, Hdc, SelectedObject};
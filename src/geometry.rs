@@ -0,0 +1,90 @@
+use windows::Win32::Foundation::RECT;
+
+// Rectangle given by its top left corner and size. The coordinate space
+// depends on where the rect comes from: screen coordinates for window and
+// monitor bounds, buffer coordinates (origin at the top left pixel) for
+// RgbBuf operations. right() and bottom() are exclusive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct WindowRect {
+    pub x: i32,
+    pub y: i32,
+    pub width: i32,
+    pub height: i32,
+}
+
+impl WindowRect {
+    pub fn right(&self) -> i32 {
+        self.x.saturating_add(self.width)
+    }
+
+    pub fn bottom(&self) -> i32 {
+        self.y.saturating_add(self.height)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.width <= 0 || self.height <= 0
+    }
+
+    pub fn area(&self) -> u64 {
+        match self.is_empty() {
+            true => 0,
+            false => self.width as u64 * self.height as u64,
+        }
+    }
+
+    pub fn contains_point(&self, x: i32, y: i32) -> bool {
+        x >= self.x && x < self.right() && y >= self.y && y < self.bottom()
+    }
+
+    // None if the rects don't overlap
+    pub fn intersect(&self, other: &WindowRect) -> Option<WindowRect> {
+        let x = self.x.max(other.x);
+        let y = self.y.max(other.y);
+        let rect = WindowRect {
+            x,
+            y,
+            width: self.right().min(other.right()) - x,
+            height: self.bottom().min(other.bottom()) - y,
+        };
+        match rect.is_empty() {
+            true => None,
+            false => Some(rect),
+        }
+    }
+}
+
+// Screen position of the top left pixel of a display capture. It is negative
+// when a monitor sits left of or above the primary one, so screen
+// coordinates have to be shifted by it to index into the capture.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct VirtualScreenOrigin {
+    pub x: i32,
+    pub y: i32,
+}
+
+impl VirtualScreenOrigin {
+    pub fn to_buffer(&self, x: i32, y: i32) -> (i32, i32) {
+        (x - self.x, y - self.y)
+    }
+
+    pub fn to_screen(&self, x: i32, y: i32) -> (i32, i32) {
+        (x + self.x, y + self.y)
+    }
+
+    // screen rect (e.g. a window rect) to display capture coordinates
+    pub fn rect_to_buffer(&self, rect: WindowRect) -> WindowRect {
+        let (x, y) = self.to_buffer(rect.x, rect.y);
+        WindowRect { x, y, ..rect }
+    }
+}
+
+impl From<RECT> for WindowRect {
+    fn from(rect: RECT) -> Self {
+        WindowRect {
+            x: rect.left,
+            y: rect.top,
+            width: rect.right - rect.left,
+            height: rect.bottom - rect.top,
+        }
+    }
+}
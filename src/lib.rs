@@ -5,9 +5,30 @@
 // https://stackoverflow.com/questions/36261725/how-to-extract-a-part-of-hbitmap-without-using-bitblt
 // https://stackoverflow.com/questions/3671008/crop-function-bitblt
 
+#[cfg(feature = "image")]
+pub mod baseline;
+pub mod buffer;
 pub mod capture;
+#[cfg(feature = "channel")]
+pub mod channel;
+pub mod dwm;
+mod encode;
+mod font;
+pub mod gdi;
+pub mod geometry;
+pub mod monitor;
+pub mod options;
 pub mod prelude;
+pub mod queue;
+#[cfg(feature = "gif")]
+pub mod recorder;
+pub mod session;
+pub mod shared;
 #[cfg(test)]
 mod tests;
+mod trace;
 pub mod utils;
+pub mod video;
+pub mod wait;
+pub mod watch;
 mod wrappers;
@@ -0,0 +1,328 @@
+use std::mem::size_of;
+use windows::core::PCWSTR;
+use windows::Win32::Foundation::{BOOL, E_FAIL, HWND, LPARAM, RECT};
+use windows::Win32::Graphics::Gdi::{
+    EnumDisplayMonitors, EnumDisplaySettingsW, GetMonitorInfoW, MonitorFromWindow, DEVMODEW,
+    ENUM_CURRENT_SETTINGS, HDC, HMONITOR, MONITORINFO, MONITORINFOEXW, MONITOR_DEFAULTTONEAREST,
+    MONITOR_DEFAULTTONULL,
+};
+use windows::Win32::UI::HiDpi::{GetDpiForMonitor, MDT_EFFECTIVE_DPI};
+use windows::Win32::UI::WindowsAndMessaging::{
+    GetForegroundWindow, GetWindowLongW, GWL_EXSTYLE, GWL_STYLE, MONITORINFOF_PRIMARY, WS_CAPTION,
+    WS_EX_TOPMOST, WS_THICKFRAME,
+};
+
+use crate::capture::{
+    capture_region, capture_screen_scaled, capture_window, RgbBuf, StretchQuality, WSError,
+};
+use crate::geometry::WindowRect;
+use crate::utils::set_dpi_awareness;
+use crate::wrappers::Rect;
+
+#[derive(Debug, Clone)]
+pub struct MonitorInfo {
+    pub hmonitor: isize,
+    // e.g. \\.\DISPLAY2, more stable across reconnects than the index
+    pub device_name: String,
+    // virtual screen coordinates
+    pub rect: WindowRect,
+    pub work_area: WindowRect,
+    pub primary: bool,
+}
+
+// where a monitor landed in capture_desktop_packed
+#[derive(Debug, Clone)]
+pub struct PackedMonitor {
+    pub monitor: MonitorInfo,
+    // buffer coordinates
+    pub rect: WindowRect,
+}
+
+unsafe extern "system" fn lm_callback(
+    hmonitor: HMONITOR,
+    _hdc: HDC,
+    _rect: *mut RECT,
+    lparam: LPARAM,
+) -> BOOL {
+    let vec = lparam.0 as *mut Vec<MonitorInfo>;
+
+    let mut info = MONITORINFOEXW::default();
+    info.monitorInfo.cbSize = size_of::<MONITORINFOEXW>() as u32;
+    if GetMonitorInfoW(
+        hmonitor,
+        &mut info as *mut MONITORINFOEXW as *mut MONITORINFO,
+    ) == false
+    {
+        return BOOL::from(true);
+    }
+
+    let name_len = info
+        .szDevice
+        .iter()
+        .position(|&c| c == 0)
+        .unwrap_or(info.szDevice.len());
+
+    (*vec).push(MonitorInfo {
+        hmonitor: hmonitor.0,
+        device_name: String::from_utf16_lossy(&info.szDevice[..name_len]),
+        rect: info.monitorInfo.rcMonitor.into(),
+        work_area: info.monitorInfo.rcWork.into(),
+        primary: info.monitorInfo.dwFlags & MONITORINFOF_PRIMARY != 0,
+    });
+
+    BOOL::from(true)
+}
+
+pub fn list_monitors() -> Result<Vec<MonitorInfo>, WSError> {
+    enum_monitors(None)
+}
+
+// The order indices passed to capture_monitor, capture_monitor_scaled and
+// capture_region_on_monitor refer to: the primary monitor is always index 0,
+// the rest follow by top left corner, top to bottom then left to right.
+// EnumDisplayMonitors itself makes no promise about its order.
+pub fn list_monitors_ordered() -> Result<Vec<MonitorInfo>, WSError> {
+    let mut monitors = enum_monitors(None)?;
+    monitors.sort_by_key(|m| (!m.primary, m.rect.y, m.rect.x));
+    Ok(monitors)
+}
+
+// only the monitors intersecting clip when it is given
+fn enum_monitors(clip: Option<&RECT>) -> Result<Vec<MonitorInfo>, WSError> {
+    set_dpi_awareness();
+    let mut monitors = Vec::new();
+    unsafe {
+        let edm = EnumDisplayMonitors(
+            HDC::default(),
+            clip.map(|r| r as *const RECT),
+            Some(lm_callback),
+            LPARAM(&mut monitors as *mut Vec<MonitorInfo> as isize),
+        );
+        if edm == false {
+            return Err(WSError::EnumDisplayMonitorsError);
+        }
+    }
+    Ok(monitors)
+}
+
+pub fn capture_monitor(index: usize) -> Result<RgbBuf, WSError> {
+    let monitor = list_monitors_ordered()?
+        .into_iter()
+        .nth(index)
+        .ok_or(WSError::MonitorNotFound)?;
+    Ok(capture_region(monitor.rect)?.buf)
+}
+
+// monitor scaled into a max_w x max_h box keeping its aspect ratio
+pub fn capture_monitor_scaled(
+    index: usize,
+    max_w: u32,
+    max_h: u32,
+    quality: StretchQuality,
+) -> Result<RgbBuf, WSError> {
+    let monitor = list_monitors_ordered()?
+        .into_iter()
+        .nth(index)
+        .ok_or(WSError::MonitorNotFound)?;
+    capture_screen_scaled(monitor.rect, max_w, max_h, quality)
+}
+
+// x and y are relative to the top left corner of the monitor, the region is
+// clipped to the monitor
+pub fn capture_region_on_monitor(
+    monitor_index: usize,
+    x: i32,
+    y: i32,
+    w: i32,
+    h: i32,
+) -> Result<RgbBuf, WSError> {
+    let monitor = list_monitors_ordered()?
+        .into_iter()
+        .nth(monitor_index)
+        .ok_or(WSError::MonitorNotFound)?;
+    let region = WindowRect {
+        x: monitor.rect.x.saturating_add(x),
+        y: monitor.rect.y.saturating_add(y),
+        width: w,
+        height: h,
+    };
+    let region = region
+        .intersect(&monitor.rect)
+        .ok_or(WSError::RegionOutOfBounds)?;
+    Ok(capture_region(region)?.buf)
+}
+
+// device_name as in MonitorInfo, e.g. \\.\DISPLAY2
+pub fn capture_monitor_by_name(device_name: &str) -> Result<RgbBuf, WSError> {
+    let monitor = list_monitors()?
+        .into_iter()
+        .find(|m| m.device_name == device_name)
+        .ok_or(WSError::MonitorNotFound)?;
+    Ok(capture_region(monitor.rect)?.buf)
+}
+
+// Borderless window exactly covering its monitor that is topmost or in the
+// foreground, the way fullscreen games and video players present.
+pub fn is_fullscreen_window(hwnd: isize) -> bool {
+    set_dpi_awareness();
+    let handle = HWND(hwnd);
+    unsafe {
+        let mut info = MONITORINFO {
+            cbSize: size_of::<MONITORINFO>() as u32,
+            ..Default::default()
+        };
+        let hmonitor = MonitorFromWindow(handle, MONITOR_DEFAULTTONULL);
+        if hmonitor.is_invalid() || GetMonitorInfoW(hmonitor, &mut info) == false {
+            return false;
+        }
+        let Ok(window) = Rect::get_window_rect(handle) else {
+            return false;
+        };
+        let monitor = info.rcMonitor;
+        let covers = window.left <= monitor.left
+            && window.top <= monitor.top
+            && window.right >= monitor.right
+            && window.bottom >= monitor.bottom;
+
+        // the styles fit in 32 bits, GetWindowLongPtrW only exists on 64-bit
+        let style = GetWindowLongW(handle, GWL_STYLE) as u32;
+        let borderless = style & (WS_CAPTION.0 | WS_THICKFRAME.0) == 0;
+        let ex_style = GetWindowLongW(handle, GWL_EXSTYLE) as u32;
+        let on_top = ex_style & WS_EX_TOPMOST.0 != 0 || GetForegroundWindow() == handle;
+
+        covers && borderless && on_top
+    }
+}
+
+// Fullscreen windows (see is_fullscreen_window) are captured from their
+// monitor, PrintWindow usually returns black for them. Everything else goes
+// through capture_window. Exclusive fullscreen DirectX games bypass the
+// desktop compositor and may still come back black or stale.
+pub fn capture_window_smart(hwnd: isize) -> Result<RgbBuf, WSError> {
+    if !is_fullscreen_window(hwnd) {
+        return Ok(capture_window(hwnd)?);
+    }
+    let hmonitor = unsafe { MonitorFromWindow(HWND(hwnd), MONITOR_DEFAULTTONULL) };
+    let monitor = list_monitors()?
+        .into_iter()
+        .find(|m| m.hmonitor == hmonitor.0)
+        .ok_or(WSError::MonitorNotFound)?;
+    Ok(capture_region(monitor.rect)?.buf)
+}
+
+// The whole monitor the foreground window is on. There is no foreground
+// window while the secure desktop (UAC, lock screen) is up.
+pub fn capture_active_monitor() -> Result<RgbBuf, WSError> {
+    let foreground = unsafe { GetForegroundWindow() };
+    if foreground.0 == 0 {
+        return Err(WSError::SecureDesktopActive);
+    }
+    let hmonitor = unsafe { MonitorFromWindow(foreground, MONITOR_DEFAULTTONEAREST) };
+    let monitor = list_monitors()?
+        .into_iter()
+        .find(|m| m.hmonitor == hmonitor.0)
+        .ok_or(WSError::MonitorNotFound)?;
+    Ok(capture_region(monitor.rect)?.buf)
+}
+
+// Captures every monitor and packs them left to right (ordered by their
+// screen position) top aligned with no gaps, instead of the mostly empty
+// bounding box capture_display returns for unaligned setups. Space below
+// monitors shorter than the tallest one is black.
+pub fn capture_desktop_packed() -> Result<(RgbBuf, Vec<PackedMonitor>), WSError> {
+    let mut monitors = list_monitors()?;
+    monitors.sort_by_key(|m| (m.rect.x, m.rect.y));
+
+    let captures = monitors
+        .iter()
+        .map(|m| Ok(capture_region(m.rect)?.buf))
+        .collect::<Result<Vec<_>, WSError>>()?;
+
+    let width = captures.iter().map(|c| c.width).sum();
+    let height = captures.iter().map(|c| c.height).max().unwrap_or(0);
+    let mut packed = RgbBuf {
+        pixels: [0, 0, 0, 255].repeat((width * height) as usize),
+        width,
+        height,
+    };
+
+    let mut layout = Vec::with_capacity(monitors.len());
+    let mut x = 0;
+    for (monitor, capture) in monitors.into_iter().zip(&captures) {
+        packed.blit(capture, x, 0);
+        layout.push(PackedMonitor {
+            monitor,
+            rect: WindowRect {
+                x,
+                y: 0,
+                width: capture.width as i32,
+                height: capture.height as i32,
+            },
+        });
+        x += capture.width as i32;
+    }
+    Ok((packed, layout))
+}
+
+// effective DPI, 96 is 100% scaling
+pub fn get_dpi_for_monitor(hmonitor: isize) -> Result<u32, WSError> {
+    let (mut dpi_x, mut dpi_y) = (0, 0);
+    unsafe {
+        GetDpiForMonitor(
+            HMONITOR(hmonitor),
+            MDT_EFFECTIVE_DPI,
+            &mut dpi_x,
+            &mut dpi_y,
+        )?;
+    }
+    Ok(dpi_x)
+}
+
+// Refresh rate in Hz of the monitor at index (see list_monitors_ordered) in
+// its current display mode. Variable refresh rate displays report their
+// nominal rate, the actual one changes frame by frame.
+pub fn get_monitor_refresh_rate(monitor_index: usize) -> Result<u32, WSError> {
+    let monitor = list_monitors_ordered()?
+        .into_iter()
+        .nth(monitor_index)
+        .ok_or(WSError::MonitorNotFound)?;
+    let name: Vec<u16> = monitor.device_name.encode_utf16().chain([0]).collect();
+    let mut mode = DEVMODEW {
+        dmSize: size_of::<DEVMODEW>() as u16,
+        ..Default::default()
+    };
+    unsafe {
+        if EnumDisplaySettingsW(PCWSTR(name.as_ptr()), ENUM_CURRENT_SETTINGS, &mut mode) == false {
+            return Err(windows::core::Error::from_win32().into());
+        }
+    }
+    // 0 and 1 mean the hardware default, the driver didn't say which
+    match mode.dmDisplayFrequency {
+        0 | 1 => Err(windows::core::Error::new(E_FAIL, "refresh rate not reported".into()).into()),
+        hz => Ok(hz),
+    }
+}
+
+// The monitors a window overlaps if they don't all have the same DPI.
+// Windows renders the window at the DPI of the monitor it overlaps most, so
+// captures come out at that scale and the part on the other monitor looks
+// too large or too small on screen. CaptureOptions::reject_mixed_dpi turns
+// this into a MixedDpiSpan error instead.
+pub fn mixed_dpi_monitors(hwnd: isize) -> Result<Option<Vec<MonitorInfo>>, WSError> {
+    let rect = Rect::get_window_rect(HWND(hwnd))?;
+    let clip = RECT {
+        left: rect.left,
+        top: rect.top,
+        right: rect.right,
+        bottom: rect.bottom,
+    };
+    let monitors = enum_monitors(Some(&clip))?;
+    let dpis = monitors
+        .iter()
+        .map(|m| get_dpi_for_monitor(m.hmonitor))
+        .collect::<Result<Vec<_>, _>>()?;
+    match dpis.windows(2).any(|d| d[0] != d[1]) {
+        true => Ok(Some(monitors)),
+        false => Ok(None),
+    }
+}
@@ -0,0 +1,100 @@
+use crate::capture::{Area, Using};
+use crate::geometry::WindowRect;
+
+// Settings for capture_window_with. Defaults match capture_window:
+// PrintWindow, full window, no crop.
+//
+// CaptureOptions::new().using(Using::BitBlt).area(Area::ClientOnly).crop([100, 100], [300, 300])
+#[derive(Debug, Clone)]
+pub struct CaptureOptions {
+    pub(crate) using: Using,
+    pub(crate) area: Area,
+    pub(crate) crop_xy: Option<[i32; 2]>,
+    pub(crate) crop_wh: Option<[i32; 2]>,
+    pub(crate) strict: bool,
+    pub(crate) reject_mixed_dpi: bool,
+    pub(crate) exclude: Vec<WindowRect>,
+    pub(crate) exclude_fill: [u8; 3],
+    pub(crate) trim_shadow: bool,
+}
+
+impl Default for CaptureOptions {
+    fn default() -> Self {
+        CaptureOptions {
+            using: Using::PrintWindow,
+            area: Area::Full,
+            crop_xy: None,
+            crop_wh: None,
+            strict: false,
+            reject_mixed_dpi: false,
+            exclude: Vec::new(),
+            exclude_fill: [0, 0, 0],
+            trim_shadow: false,
+        }
+    }
+}
+
+impl CaptureOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn using(mut self, using: Using) -> Self {
+        self.using = using;
+        self
+    }
+
+    pub fn area(mut self, area: Area) -> Self {
+        self.area = area;
+        self
+    }
+
+    // crop offset and size relative to the captured area
+    pub fn crop(mut self, xy: [i32; 2], wh: [i32; 2]) -> Self {
+        self.crop_xy = Some(xy);
+        self.crop_wh = Some(wh);
+        self
+    }
+
+    pub fn crop_xy(mut self, xy: [i32; 2]) -> Self {
+        self.crop_xy = Some(xy);
+        self
+    }
+
+    pub fn crop_wh(mut self, wh: [i32; 2]) -> Self {
+        self.crop_wh = Some(wh);
+        self
+    }
+
+    // fail with WSError::BlankResult instead of returning a uniform frame
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    // fail with WSError::MixedDpiSpan if the window overlaps monitors with
+    // different DPI, see mixed_dpi_monitors
+    pub fn reject_mixed_dpi(mut self, reject: bool) -> Self {
+        self.reject_mixed_dpi = reject;
+        self
+    }
+
+    // Rects relative to the captured area (like crop) that are left out of
+    // the capture and filled with fill instead, e.g. a video overlay child.
+    // BitBlt clips them out with ExcludeClipRect before the blit, the other
+    // methods can't clip and overwrite them afterwards.
+    pub fn exclude(mut self, rects: Vec<WindowRect>, fill: [u8; 3]) -> Self {
+        self.exclude = rects;
+        self.exclude_fill = fill;
+        self
+    }
+
+    // Full window captures (not BitBlt, which is always client only) are
+    // cropped to the visible frame, DWMWA_EXTENDED_FRAME_BOUNDS, dropping the
+    // transparent resize border and shadow margin. crop is then relative to
+    // the visible frame. Off by default to keep the old output.
+    pub fn trim_shadow(mut self, trim: bool) -> Self {
+        self.trim_shadow = trim;
+        self
+    }
+}
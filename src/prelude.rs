@@ -1,4 +1,51 @@
 pub use super::{
-    capture::{capture_display, capture_window, capture_window_ex, Area, RgbBuf, Using, WSError},
-    utils::{find_window, window_list, FWError, HwndName, WLError},
+    buffer::{RawFormat, RedactMode, Rotation},
+    capture::{
+        capture_control, capture_display, capture_display_bench, capture_display_dirty,
+        capture_display_into_buffer_ex, capture_display_scaled, capture_display_with_origin,
+        capture_region, capture_window, capture_window_bgra, capture_window_bgra_with,
+        capture_window_ex, capture_window_full, capture_window_full_and_client,
+        capture_window_into_bgra, capture_window_into_strided_buffer, capture_window_non_client,
+        capture_window_render_scaled, capture_window_restored, capture_window_strict,
+        capture_window_thumbnail_hq, capture_window_thumbnail_sized, capture_window_timeout,
+        capture_window_with, capture_windows_tiled, get_pixel_color, get_window_icon, Area,
+        BgraBuf, Capture, CaptureBench, DisplayCapture, PixelFormat, RegionCapture, RgbBuf,
+        StretchQuality, Using, WSError, WindowSize,
+    },
+    dwm::capture_window_dwm,
+    geometry::{VirtualScreenOrigin, WindowRect},
+    monitor::{
+        capture_active_monitor, capture_desktop_packed, capture_monitor, capture_monitor_by_name,
+        capture_monitor_scaled, capture_region_on_monitor, capture_window_smart,
+        get_dpi_for_monitor, get_monitor_refresh_rate, is_fullscreen_window, list_monitors,
+        list_monitors_ordered, mixed_dpi_monitors, MonitorInfo, PackedMonitor,
+    },
+    options::CaptureOptions,
+    queue::ScreenshotQueue,
+    session::{CaptureFrame, CaptureSession},
+    shared::{capture_window_to_shared, SharedCapture},
+    utils::{
+        find_best_window, find_largest_window, find_window, get_dpi_for_window,
+        get_visible_window_rect, get_window_bounds_in_dips, get_window_process_path,
+        get_window_thread_process_id, is_capture_protected, is_secure_desktop_active,
+        is_window_on_current_desktop, primary_resolution, virtual_screen_bounds, window_list,
+        window_list_ex, window_list_filtered, window_list_with_bounds, windows_for_pid, FWError,
+        HwndName, SendWindow, WLError, WindowFilter,
+    },
+    video::{FramePolicy, Recording, RecordingStats, VideoRecorder},
+    wait::{wait_for_pixel, wait_for_region_change, wait_for_region_change_ex},
+    watch::{watch_windows, WindowEvent},
 };
+
+#[cfg(feature = "image")]
+pub use super::baseline::{capture_window_assert_matches, DiffReport, DiffStatus};
+#[cfg(feature = "channel")]
+pub use super::channel::spawn_capture_channel;
+#[cfg(feature = "image")]
+pub use super::encode::SizedJpeg;
+
+#[cfg(feature = "gif")]
+pub use super::recorder::FrameRecorder;
+
+#[cfg(feature = "uiautomation")]
+pub use super::utils::find_window_by_automation_id;
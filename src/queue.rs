@@ -0,0 +1,78 @@
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::Mutex;
+use std::thread::{self, JoinHandle};
+
+use crate::capture::{capture_window_with, RgbBuf, WSError};
+use crate::options::CaptureOptions;
+use crate::utils::set_dpi_awareness;
+
+struct Request {
+    hwnd: isize,
+    options: CaptureOptions,
+    reply: Sender<Result<RgbBuf, WSError>>,
+}
+
+// Runs every capture on one dedicated thread, in the order they were
+// requested. Any thread can enqueue through a shared reference and gets a
+// receiver that yields exactly one result. Dropping the queue finishes the
+// pending requests and joins the thread.
+pub struct ScreenshotQueue {
+    sender: Option<Mutex<Sender<Request>>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl ScreenshotQueue {
+    pub fn new() -> ScreenshotQueue {
+        let (sender, receiver) = channel::<Request>();
+        let thread = thread::spawn(move || {
+            set_dpi_awareness();
+            for request in receiver {
+                let result = capture_window_with(request.hwnd, &request.options);
+                // the caller may have dropped its receiver, that's fine
+                let _ = request.reply.send(result);
+            }
+        });
+        ScreenshotQueue {
+            sender: Some(Mutex::new(sender)),
+            thread: Some(thread),
+        }
+    }
+
+    pub fn capture(&self, hwnd: isize) -> Receiver<Result<RgbBuf, WSError>> {
+        self.capture_with(hwnd, CaptureOptions::default())
+    }
+
+    pub fn capture_with(
+        &self,
+        hwnd: isize,
+        options: CaptureOptions,
+    ) -> Receiver<Result<RgbBuf, WSError>> {
+        let (reply, result) = channel();
+        let request = Request {
+            hwnd,
+            options,
+            reply,
+        };
+        // the thread only stops when the queue is dropped, sending can't fail
+        if let Some(sender) = &self.sender {
+            let _ = sender.lock().unwrap().send(request);
+        }
+        result
+    }
+}
+
+impl Default for ScreenshotQueue {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for ScreenshotQueue {
+    fn drop(&mut self) {
+        // closing the channel ends the loop on the capture thread
+        self.sender = None;
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
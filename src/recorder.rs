@@ -0,0 +1,89 @@
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+
+use gif::{Encoder, Frame, Repeat};
+
+use crate::capture::{RgbBuf, WSError};
+
+// Collects frames and writes them as an animated GIF. Every frame is
+// padded (with black) or cropped to the size of the first one.
+pub struct FrameRecorder {
+    frames: Vec<(RgbBuf, u32)>,
+    speed: i32,
+}
+
+impl Default for FrameRecorder {
+    fn default() -> Self {
+        FrameRecorder {
+            frames: Vec::new(),
+            speed: 10,
+        }
+    }
+}
+
+impl FrameRecorder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // NeuQuant color quantization speed, 1 (best colors, slowest) to 30
+    pub fn quantization_speed(mut self, speed: i32) -> Self {
+        self.speed = speed.clamp(1, 30);
+        self
+    }
+
+    // delay_ms until the next frame, GIF stores it in 10ms steps
+    pub fn push(&mut self, buf: RgbBuf, delay_ms: u32) {
+        let buf = match self.frames.first() {
+            Some((first, _)) if first.width != buf.width || first.height != buf.height => {
+                let mut padded = RgbBuf {
+                    pixels: [0, 0, 0, 255].repeat((first.width * first.height) as usize),
+                    width: first.width,
+                    height: first.height,
+                };
+                padded.blit(&buf, 0, 0);
+                padded
+            }
+            _ => buf,
+        };
+        self.frames.push((buf, delay_ms));
+    }
+
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), WSError> {
+        let Some((first, _)) = self.frames.first() else {
+            return Err(WSError::EncodeError);
+        };
+        let file = BufWriter::new(File::create(path)?);
+        let mut encoder = Encoder::new(file, first.width as u16, first.height as u16, &[])
+            .map_err(|_| WSError::EncodeError)?;
+        encoder
+            .set_repeat(Repeat::Infinite)
+            .map_err(|_| WSError::EncodeError)?;
+
+        for (buf, delay_ms) in &self.frames {
+            // GDI leaves alpha undefined, a 0 would make the pixel transparent
+            let mut pixels = buf.pixels.clone();
+            pixels.chunks_exact_mut(4).for_each(|p| p[3] = 255);
+            let mut frame = Frame::from_rgba_speed(
+                buf.width as u16,
+                buf.height as u16,
+                &mut pixels,
+                self.speed,
+            );
+            frame.delay = (delay_ms / 10).min(u16::MAX as u32) as u16;
+            encoder
+                .write_frame(&frame)
+                .map_err(|_| WSError::EncodeError)?;
+        }
+        Ok(())
+    }
+}
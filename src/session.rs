@@ -0,0 +1,267 @@
+use std::mem::size_of;
+use std::slice;
+use windows::Win32::Foundation::{HANDLE, HWND};
+use windows::Win32::Graphics::Gdi::{
+    BitBlt, CreateDIBSection, GdiFlush, BITMAPINFO, BITMAPINFOHEADER, BI_RGB, DIB_RGB_COLORS, HDC,
+    SRCCOPY,
+};
+use windows::Win32::Storage::Xps::PrintWindow;
+
+use crate::buffer::content_hash;
+use crate::capture::{
+    capture_rect, check_capturable, check_window, client_rect_in_window, print_window_flags, send_print_message,
+    zero_client_rect, Area, RgbBuf, Using, WSError,
+};
+use crate::options::CaptureOptions;
+use crate::utils::set_dpi_awareness;
+use crate::wrappers::{CreatedHdc, Hbitmap, Hdc, SelectedObject};
+
+// Frame borrowed from the DIB section of a CaptureSession, valid until the
+// next capture. Pixels are B, G, R, A, rows top to bottom, stride bytes apart.
+pub struct CaptureFrame<'a> {
+    pixels: &'a [u8],
+    width: u32,
+    height: u32,
+    stride: u32,
+}
+
+impl<'a> CaptureFrame<'a> {
+    pub fn as_slice(&self) -> &'a [u8] {
+        self.pixels
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn stride(&self) -> u32 {
+        self.stride
+    }
+}
+
+// DIB section selected into the session DC, fields drop in declaration order
+struct Dib {
+    _selected: SelectedObject<'static>,
+    _hbitmap: Hbitmap,
+    bits: *const u8,
+    width: i32,
+    height: i32,
+}
+
+impl Dib {
+    unsafe fn new(hdc: &CreatedHdc, width: i32, height: i32) -> Result<Dib, WSError> {
+        let bmi = BITMAPINFO {
+            bmiHeader: BITMAPINFOHEADER {
+                biSize: size_of::<BITMAPINFOHEADER>() as u32,
+                biPlanes: 1,
+                biBitCount: 32,
+                biWidth: width,
+                biHeight: -height,
+                biCompression: BI_RGB.0 as u32,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let mut bits = std::ptr::null_mut();
+        let hbitmap = Hbitmap {
+            hbitmap: CreateDIBSection(hdc.hdc, &bmi, DIB_RGB_COLORS, &mut bits, HANDLE(0), 0)?,
+        };
+        let selected = SelectedObject::select_raw(HDC::from(hdc), hbitmap.hbitmap)?;
+        Ok(Dib {
+            _selected: selected,
+            _hbitmap: hbitmap,
+            bits: bits as *const u8,
+            width,
+            height,
+        })
+    }
+}
+
+// Repeated captures of one window into a persistent DIB section. Frames are
+// read straight from GDI memory: no GetDIBits copy, no red/blue swap and no
+// allocation unless the window changes size. Crop settings of the options
+// are ignored.
+pub struct CaptureSession {
+    hwnd: HWND,
+    options: CaptureOptions,
+    // must be dropped before the DC it is selected into
+    dib: Option<Dib>,
+    hdc: CreatedHdc,
+    anchored: bool,
+    anchor: Option<Anchor>,
+    // content hash of the BGRA frame and its RGBA copy, see capture_if_changed
+    last: Option<(u64, RgbBuf)>,
+}
+
+// output frame of an anchored session, fixed to the size of the first capture
+struct Anchor {
+    // client area origin within the first frame
+    origin: (i32, i32),
+    width: u32,
+    height: u32,
+    pixels: Vec<u8>,
+}
+
+impl CaptureSession {
+    pub fn new(hwnd: isize) -> Result<CaptureSession, WSError> {
+        CaptureSession::with_options(hwnd, CaptureOptions::new())
+    }
+
+    pub fn with_options(hwnd: isize, options: CaptureOptions) -> Result<CaptureSession, WSError> {
+        check_window(hwnd)?;
+        set_dpi_awareness();
+        Ok(CaptureSession {
+            hwnd: HWND(hwnd),
+            options,
+            dib: None,
+            hdc: CreatedHdc::create_compatible_dc(HDC::default())?,
+            anchored: false,
+            anchor: None,
+            last: None,
+        })
+    }
+
+    // Every frame keeps the size of the first one and the client area stays
+    // at the position it had there, the window rect is re-read each capture.
+    // Moving the window never shifts the content, but resizing it, or the
+    // non-client area changing (e.g. a menu bar appearing), would otherwise
+    // resize the frame or move the content within it. A window that grew is
+    // cut off at the right and bottom, one that shrank is padded with black.
+    pub fn anchored(mut self, anchored: bool) -> Self {
+        self.anchored = anchored;
+        self.anchor = None;
+        self
+    }
+
+    // Captures a frame and returns it as RGBA, or None if its content hash
+    // matches the previous frame returned here. An unchanged frame is only
+    // hashed, the copy and red/blue swap are skipped. The returned buffer is
+    // reused for the next changed frame.
+    pub fn capture_if_changed(&mut self) -> Result<Option<&RgbBuf>, WSError> {
+        let last = self.last.take();
+        let frame = match self.capture() {
+            Ok(frame) => frame,
+            Err(e) => {
+                self.last = last;
+                return Err(e);
+            }
+        };
+        let hash = content_hash(frame.as_slice(), frame.width(), frame.height());
+        let mut buf = match last {
+            Some((last_hash, buf)) if last_hash == hash => {
+                self.last = Some((last_hash, buf));
+                return Ok(None);
+            }
+            Some((_, buf)) => buf,
+            None => RgbBuf {
+                pixels: Vec::new(),
+                width: 0,
+                height: 0,
+            },
+        };
+        buf.pixels.clear();
+        for row in frame.as_slice().chunks_exact(frame.stride() as usize) {
+            buf.pixels
+                .extend_from_slice(&row[..frame.width() as usize * 4]);
+        }
+        buf.pixels.chunks_exact_mut(4).for_each(|c| c.swap(0, 2));
+        buf.width = frame.width();
+        buf.height = frame.height();
+        self.last = Some((hash, buf));
+        Ok(self.last.as_ref().map(|(_, buf)| buf))
+    }
+
+    pub fn capture(&mut self) -> Result<CaptureFrame<'_>, WSError> {
+        check_capturable(self.hwnd.0)?;
+        let (using, area) = (self.options.using, self.options.area);
+        unsafe {
+            // see capture_rect
+            let hdc_window = match using == Using::BitBlt && zero_client_rect(self.hwnd).is_some() {
+                true => Hdc::get_window_dc(self.hwnd)?,
+                false => Hdc::get_dc(self.hwnd)?,
+            };
+            let rect = capture_rect(self.hwnd, using, area)?;
+
+            let resized = match &self.dib {
+                Some(dib) => dib.width != rect.width || dib.height != rect.height,
+                None => true,
+            };
+            if resized {
+                // the old DIB has to be deselected before the new one is selected
+                self.dib = None;
+                self.dib = Some(Dib::new(&self.hdc, rect.width, rect.height)?);
+            }
+
+            match using {
+                Using::BitBlt => {
+                    let (w, h) = (rect.width, rect.height);
+                    if BitBlt(self.hdc.hdc, 0, 0, w, h, hdc_window.hdc, 0, 0, SRCCOPY) == false {
+                        return Err(windows::core::Error::from_win32().into());
+                    }
+                }
+                // no screen fallback here, the frame has to come from the DIB
+                Using::PrintWindow | Using::Auto => {
+                    if PrintWindow(self.hwnd, self.hdc.hdc, print_window_flags(area)) == false {
+                        return Err(windows::core::Error::from_win32().into());
+                    }
+                }
+                Using::WmPrint => send_print_message(self.hwnd, HDC::from(&self.hdc), area),
+            }
+            // make sure GDI is done writing before the bits are read
+            GdiFlush();
+
+            let dib = self.dib.as_ref().unwrap();
+            let stride = dib.width as u32 * 4;
+            let pixels = slice::from_raw_parts(dib.bits, (stride * dib.height as u32) as usize);
+            if !self.anchored {
+                return Ok(CaptureFrame {
+                    pixels,
+                    width: dib.width as u32,
+                    height: dib.height as u32,
+                    stride,
+                });
+            }
+
+            // the client area is at the frame origin unless the frame
+            // includes the non-client area
+            let origin = match (using, area) {
+                (Using::BitBlt, _) | (_, Area::ClientOnly) => (0, 0),
+                (_, Area::Full) => {
+                    let client = client_rect_in_window(self.hwnd)?;
+                    (client.x, client.y)
+                }
+            };
+            let anchor = self.anchor.get_or_insert_with(|| Anchor {
+                origin,
+                width: dib.width as u32,
+                height: dib.height as u32,
+                pixels: vec![0; (stride * dib.height as u32) as usize],
+            });
+            let (aw, ah) = (anchor.width as i32, anchor.height as i32);
+            let (dx, dy) = (anchor.origin.0 - origin.0, anchor.origin.1 - origin.1);
+            anchor
+                .pixels
+                .chunks_exact_mut(4)
+                .for_each(|p| p.copy_from_slice(&[0, 0, 0, 255]));
+            let (x0, x1) = (dx.max(0), (dx + dib.width).min(aw));
+            if x0 < x1 {
+                let len = ((x1 - x0) * 4) as usize;
+                for y in dy.max(0)..(dy + dib.height).min(ah) {
+                    let d = ((y * aw + x0) * 4) as usize;
+                    let s = (((y - dy) * dib.width + x0 - dx) * 4) as usize;
+                    anchor.pixels[d..d + len].copy_from_slice(&pixels[s..s + len]);
+                }
+            }
+            Ok(CaptureFrame {
+                pixels: &anchor.pixels,
+                width: anchor.width,
+                height: anchor.height,
+                stride: anchor.width * 4,
+            })
+        }
+    }
+}
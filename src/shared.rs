@@ -0,0 +1,98 @@
+use windows::core::PCWSTR;
+use windows::Win32::Foundation::{CloseHandle, HANDLE, INVALID_HANDLE_VALUE};
+use windows::Win32::System::Memory::{
+    CreateFileMappingW, MapViewOfFile, UnmapViewOfFile, FILE_MAP_ALL_ACCESS,
+    MEMORYMAPPEDVIEW_HANDLE, PAGE_READWRITE,
+};
+
+use crate::buffer::BYTES_HEADER_LEN;
+use crate::capture::{capture_window, PixelFormat, WSError};
+
+// A capture in a named, pagefile backed file mapping. The mapping holds
+// exactly what RgbBuf::to_bytes returns: the 13 byte header (magic, little
+// endian width and height, format) followed by the RGBA pixels, so another
+// process opens it with OpenFileMappingW(name) and reads it with
+// RgbBuf::from_bytes or straight from the view. The mapping exists until
+// this and every other handle to it are closed.
+pub struct SharedCapture {
+    name: String,
+    width: u32,
+    height: u32,
+    mapping: HANDLE,
+    view: MEMORYMAPPEDVIEW_HANDLE,
+}
+
+impl SharedCapture {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn format(&self) -> PixelFormat {
+        PixelFormat::Rgba
+    }
+
+    // pixels start at this offset in the mapping
+    pub fn header_len(&self) -> usize {
+        BYTES_HEADER_LEN
+    }
+
+    // header and pixels as another process sees them
+    pub fn as_bytes(&self) -> &[u8] {
+        let len = BYTES_HEADER_LEN + (self.width * self.height * 4) as usize;
+        unsafe { std::slice::from_raw_parts(self.view.0 as *const u8, len) }
+    }
+}
+
+impl Drop for SharedCapture {
+    fn drop(&mut self) {
+        unsafe {
+            UnmapViewOfFile(self.view);
+            CloseHandle(self.mapping);
+        }
+    }
+}
+
+// Captures the window (as capture_window) into a new file mapping called
+// name, e.g. "Local\\my-overlay-frame". An existing mapping of that name
+// is written to instead, which fails if it is too small for the capture.
+pub fn capture_window_to_shared(hwnd: isize, name: &str) -> Result<SharedCapture, WSError> {
+    let buf = capture_window(hwnd)?;
+    let len = BYTES_HEADER_LEN + buf.pixels.len();
+    let wide: Vec<u16> = name.encode_utf16().chain([0]).collect();
+    unsafe {
+        let mapping = CreateFileMappingW(
+            INVALID_HANDLE_VALUE,
+            None,
+            PAGE_READWRITE,
+            (len as u64 >> 32) as u32,
+            len as u32,
+            PCWSTR(wide.as_ptr()),
+        )?;
+        let view = match MapViewOfFile(mapping, FILE_MAP_ALL_ACCESS, 0, 0, len) {
+            Ok(view) => view,
+            Err(e) => {
+                CloseHandle(mapping);
+                return Err(e.into());
+            }
+        };
+        let shared = SharedCapture {
+            name: name.to_string(),
+            width: buf.width,
+            height: buf.height,
+            mapping,
+            view,
+        };
+        let dst = std::slice::from_raw_parts_mut(view.0 as *mut u8, len);
+        dst[..BYTES_HEADER_LEN].copy_from_slice(&buf.bytes_header());
+        dst[BYTES_HEADER_LEN..].copy_from_slice(&buf.pixels);
+        Ok(shared)
+    }
+}
@@ -47,3 +47,703 @@ fn enumerate_params() {
     cutr(hwnd, Area::Full, None, Some([100, 100]));
     cutr(hwnd, Area::Full, Some([100, 100]), Some([100, 100]));
 }
+
+#[test]
+fn capture_display_from_many_threads() {
+    let threads: Vec<_> = (0..8)
+        .map(|_| std::thread::spawn(|| capture_display().unwrap()))
+        .collect();
+    for t in threads {
+        let buf = t.join().unwrap();
+        assert_eq!(buf.pixels.len(), (buf.width * buf.height * 4) as usize);
+    }
+}
+
+fn gdi_objects() -> u32 {
+    use windows::Win32::System::Threading::{GetCurrentProcess, GetGuiResources, GR_GDIOBJECTS};
+    unsafe { GetGuiResources(GetCurrentProcess(), GR_GDIOBJECTS) }
+}
+
+#[test]
+fn no_gdi_leaks() {
+    let desktop = unsafe { windows::Win32::UI::WindowsAndMessaging::GetDesktopWindow() }.0;
+    // warm up, the first capture allocates some process wide GDI state
+    capture_window(desktop).unwrap();
+    capture_display().unwrap();
+
+    let before = gdi_objects();
+    for _ in 0..1000 {
+        capture_window(desktop).unwrap();
+        capture_window_ex(desktop, Using::PrintWindow, Area::Full, Some([10, 10]), Some([50, 50]))
+            .unwrap();
+        capture_display().unwrap();
+        // a negative crop fails in CreateCompatibleBitmap, after the DCs are created
+        assert!(capture_window_ex(desktop, Using::BitBlt, Area::Full, None, Some([-1, -1])).is_err());
+    }
+    assert_eq!(before, gdi_objects());
+}
+
+#[test]
+fn degenerate_rects() {
+    use crate::wrappers::Rect;
+    use windows::Win32::Foundation::RECT;
+
+    let swapped = Rect::from_rect(RECT {
+        left: 300,
+        top: 200,
+        right: 100,
+        bottom: 50,
+    })
+    .unwrap();
+    assert_eq!([swapped.left, swapped.top, swapped.right, swapped.bottom], [100, 50, 300, 200]);
+    assert_eq!([swapped.width, swapped.height], [200, 150]);
+
+    let empty = RECT {
+        left: 100,
+        top: 100,
+        right: 100,
+        bottom: 200,
+    };
+    let err = Rect::from_rect(empty).unwrap_err();
+    assert!(matches!(WSError::from(err), WSError::EmptyWindowRect));
+}
+
+#[test]
+fn negative_virtual_screen_origin() {
+    // monitor left of the primary one
+    let origin = VirtualScreenOrigin { x: -1920, y: -200 };
+    let window = WindowRect {
+        x: -1920,
+        y: 0,
+        width: 800,
+        height: 600,
+    };
+    let in_buffer = origin.rect_to_buffer(window);
+    assert_eq!((in_buffer.x, in_buffer.y), (0, 200));
+    assert_eq!((in_buffer.width, in_buffer.height), (800, 600));
+    assert_eq!(origin.to_screen(0, 200), (-1920, 0));
+    assert_eq!(origin.to_buffer(0, 0), (1920, 200));
+}
+
+fn assert_send_sync<T: Send + Sync>() {}
+
+#[test]
+fn thread_safety() {
+    assert_send_sync::<RgbBuf>();
+    assert_send_sync::<BgraBuf>();
+    assert_send_sync::<SendWindow>();
+    assert_send_sync::<WSError>();
+    assert_send_sync::<CaptureOptions>();
+}
+
+#[test]
+fn bytes_roundtrip() {
+    let buf = RgbBuf {
+        pixels: (0..24).collect(),
+        width: 3,
+        height: 2,
+    };
+    let bytes = buf.to_bytes();
+    let back = RgbBuf::from_bytes(&bytes).unwrap();
+    assert!(back.pixels_equal(&buf));
+
+    assert!(RgbBuf::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+    assert!(RgbBuf::from_bytes(&bytes[..5]).is_err());
+    let mut bad_magic = bytes.clone();
+    bad_magic[0] = b'X';
+    assert!(RgbBuf::from_bytes(&bad_magic).is_err());
+}
+
+#[test]
+fn invalid_window() {
+    assert!(capture_window(0).is_err());
+    assert!(matches!(
+        capture_window_with(0, &CaptureOptions::new()),
+        Err(WSError::InvalidWindow)
+    ));
+}
+
+#[test]
+fn zero_client_rect() {
+    use windows::core::w;
+    use windows::Win32::Foundation::HWND;
+    use windows::Win32::UI::WindowsAndMessaging::{
+        CreateWindowExW, DestroyWindow, WINDOW_EX_STYLE, WS_BORDER, WS_POPUP,
+    };
+
+    unsafe {
+        // the 1px border takes up the whole 2x2 window
+        let hwnd = CreateWindowExW(
+            WINDOW_EX_STYLE::default(),
+            w!("Static"),
+            w!("no client"),
+            WS_POPUP | WS_BORDER,
+            0,
+            0,
+            2,
+            2,
+            None,
+            None,
+            None,
+            None,
+        );
+        assert_ne!(hwnd, HWND(0));
+        let client_only = CaptureOptions::new().area(Area::ClientOnly);
+        match capture_window_with(hwnd.0, &client_only) {
+            Err(WSError::GetClientRectIsZero(window)) => {
+                assert_eq!((window.width, window.height), (2, 2))
+            }
+            other => panic!("expected GetClientRectIsZero, got {:?}", other.map(|b| b.width)),
+        }
+        let blitted =
+            capture_window_ex(hwnd.0, Using::BitBlt, Area::ClientOnly, None, None).unwrap();
+        assert_eq!((blitted.width, blitted.height), (2, 2));
+        DestroyWindow(hwnd);
+    }
+}
+
+#[test]
+fn srgb_to_linear() {
+    let buf = RgbBuf {
+        pixels: vec![0, 10, 128, 0, 188, 255, 64, 255],
+        width: 2,
+        height: 1,
+    };
+    let linear = buf.to_linear();
+    // reference values of the sRGB transfer function
+    let expected = [0.0, 0.003035, 0.215861, 0.502886, 1.0, 0.051269];
+    assert_eq!(linear.len(), expected.len());
+    for (value, expected) in linear.iter().zip(expected) {
+        assert!((value - expected).abs() < 1e-5, "{} != {}", value, expected);
+    }
+}
+
+#[test]
+fn rotate() {
+    // 3x2, the first byte of each pixel numbers it
+    // 1 2 3
+    // 4 5 6
+    let buf = RgbBuf {
+        pixels: (1..=6).flat_map(|n| [n, 0, 0, 255]).collect(),
+        width: 3,
+        height: 2,
+    };
+    let first_bytes = |b: &RgbBuf| b.pixels.chunks_exact(4).map(|p| p[0]).collect::<Vec<_>>();
+
+    let cw = buf.rotate(Rotation::Deg90);
+    assert_eq!((cw.width, cw.height), (2, 3));
+    assert_eq!(first_bytes(&cw), [4, 1, 5, 2, 6, 3]);
+
+    let half = buf.rotate(Rotation::Deg180);
+    assert_eq!((half.width, half.height), (3, 2));
+    assert_eq!(first_bytes(&half), [6, 5, 4, 3, 2, 1]);
+
+    let ccw = buf.rotate(Rotation::Deg270);
+    assert_eq!((ccw.width, ccw.height), (2, 3));
+    assert_eq!(first_bytes(&ccw), [3, 6, 2, 5, 1, 4]);
+
+    let back = cw.rotate(Rotation::Deg270);
+    assert!(back.pixels_equal(&buf));
+}
+
+// Needs an interactive desktop. To check the display side by hand: run an app
+// that calls SetWindowDisplayAffinity(WDA_EXCLUDEFROMCAPTURE) on a window,
+// capture_display() and the window is missing from the image while the
+// desktop behind it is visible.
+#[test]
+#[ignore]
+fn display_affinity() {
+    use windows::core::w;
+    use windows::Win32::Foundation::HWND;
+    use windows::Win32::UI::WindowsAndMessaging::{
+        CreateWindowExW, DestroyWindow, SetWindowDisplayAffinity, WDA_EXCLUDEFROMCAPTURE,
+        WS_EX_TOPMOST, WS_POPUP, WS_VISIBLE,
+    };
+
+    unsafe {
+        let hwnd = CreateWindowExW(
+            WS_EX_TOPMOST,
+            w!("Static"),
+            w!("excluded"),
+            WS_POPUP | WS_VISIBLE,
+            0,
+            0,
+            200,
+            200,
+            None,
+            None,
+            None,
+            None,
+        );
+        assert_ne!(hwnd, HWND(0));
+        assert!(!is_capture_protected(hwnd.0));
+        assert!(SetWindowDisplayAffinity(hwnd, WDA_EXCLUDEFROMCAPTURE).as_bool());
+        assert!(is_capture_protected(hwnd.0));
+        assert!(matches!(
+            capture_window_with(hwnd.0, &CaptureOptions::new()),
+            Err(WSError::CaptureProtected)
+        ));
+        DestroyWindow(hwnd);
+    }
+}
+
+#[test]
+fn redact() {
+    // 3x2, the red channel numbers the pixels
+    let new = || RgbBuf {
+        pixels: [0, 30, 60, 90, 120, 150]
+            .iter()
+            .flat_map(|&r| [r, 1, 2, 255])
+            .collect(),
+        width: 3,
+        height: 2,
+    };
+    let buf = new();
+    let reds = |b: &RgbBuf| b.pixels.chunks_exact(4).map(|p| p[0]).collect::<Vec<_>>();
+    let rect = |x, y, width, height| WindowRect {
+        x,
+        y,
+        width,
+        height,
+    };
+
+    let mut fill = new();
+    fill.redact(rect(1, 0, 2, 1), RedactMode::Fill([7, 8, 9]));
+    assert_eq!(&fill.pixels[..4], &buf.pixels[..4]);
+    assert_eq!(&fill.pixels[4..12], &[7, 8, 9, 255, 7, 8, 9, 255]);
+    assert_eq!(&fill.pixels[12..], &buf.pixels[12..]);
+
+    let mut pixelate = new();
+    pixelate.redact(rect(0, 0, 2, 2), RedactMode::Pixelate(2));
+    // (0 + 30 + 90 + 120) / 4
+    assert_eq!(reds(&pixelate), [60, 60, 60, 60, 60, 150]);
+
+    let mut blur = new();
+    blur.redact(rect(0, 0, 3, 1), RedactMode::Blur(1));
+    // samples past the ends of the line are left out of the average
+    assert_eq!(reds(&blur), [15, 30, 45, 90, 120, 150]);
+    assert!(blur.pixels.chunks_exact(4).all(|p| p[1..] == [1, 2, 255]));
+}
+
+#[test]
+fn redact_out_of_bounds() {
+    let new = || RgbBuf {
+        pixels: (0..6).flat_map(|n| [n * 10, 0, 0, 255]).collect(),
+        width: 3,
+        height: 2,
+    };
+    let buf = new();
+    let modes = [
+        RedactMode::Fill([255, 255, 255]),
+        RedactMode::Blur(5),
+        RedactMode::Pixelate(4),
+    ];
+
+    // partly outside: only the top left pixel is inside the buffer
+    let partly = WindowRect {
+        x: -1,
+        y: -1,
+        width: 2,
+        height: 2,
+    };
+    let mut fill = new();
+    fill.redact(partly, modes[0]);
+    assert_eq!(&fill.pixels[..4], &[255, 255, 255, 255]);
+    assert_eq!(&fill.pixels[4..], &buf.pixels[4..]);
+
+    let outside = [
+        WindowRect {
+            x: 3,
+            y: 0,
+            width: 10,
+            height: 10,
+        },
+        WindowRect {
+            x: -20,
+            y: -20,
+            width: 5,
+            height: 5,
+        },
+        WindowRect {
+            x: i32::MAX - 1,
+            y: i32::MAX - 1,
+            width: i32::MAX,
+            height: i32::MAX,
+        },
+    ];
+    for mode in modes {
+        // larger than the buffer on every side
+        let mut covered = new();
+        covered.redact(
+            WindowRect {
+                x: -5,
+                y: -5,
+                width: 20,
+                height: 20,
+            },
+            mode,
+        );
+        assert_eq!(covered.pixels.len(), buf.pixels.len());
+
+        for rect in outside {
+            let mut untouched = new();
+            untouched.redact(rect, mode);
+            assert!(untouched.pixels_equal(&buf));
+        }
+    }
+}
+
+// width x height buffer of one color
+fn solid(width: u32, height: u32, color: [u8; 4]) -> RgbBuf {
+    RgbBuf {
+        pixels: color.repeat((width * height) as usize),
+        width,
+        height,
+    }
+}
+
+#[test]
+fn diff_count() {
+    let a = solid(3, 2, [10, 20, 30, 255]);
+    assert_eq!(a.diff_count(&solid(3, 2, [10, 20, 30, 255]), false), Some(0));
+
+    let mut b = solid(3, 2, [10, 20, 30, 255]);
+    b.pixels[4 * 4] = 11;
+    assert_eq!(a.diff_count(&b, false), Some(1));
+    assert_eq!(a.diff_count(&b, true), Some(1));
+
+    // alpha only
+    let mut c = solid(3, 2, [10, 20, 30, 255]);
+    c.pixels[3] = 0;
+    assert_eq!(a.diff_count(&c, false), Some(1));
+    assert_eq!(a.diff_count(&c, true), Some(0));
+
+    assert_eq!(a.diff_count(&solid(2, 3, [10, 20, 30, 255]), false), None);
+}
+
+#[test]
+fn diff_count_masked() {
+    let a = solid(3, 2, [10, 20, 30, 255]);
+    let mut b = solid(3, 2, [10, 20, 30, 255]);
+    // pixel (2, 1) changed
+    b.pixels[5 * 4 + 1] = 99;
+    let covering = WindowRect {
+        x: 1,
+        y: 1,
+        width: 5,
+        height: 5,
+    };
+    let elsewhere = WindowRect {
+        x: 0,
+        y: 0,
+        width: 2,
+        height: 2,
+    };
+
+    assert_eq!(a.diff_count_masked(&b, &[]), Some(1));
+    assert_eq!(a.diff_count_masked(&b, &[covering]), Some(0));
+    assert_eq!(a.diff_count_masked(&b, &[elsewhere]), Some(1));
+    assert_eq!(a.diff_count_masked(&b, &[elsewhere, covering]), Some(0));
+    assert_eq!(a.diff_count_masked(&solid(3, 3, [0; 4]), &[covering]), None);
+}
+
+#[test]
+fn diff_ratio_tolerant() {
+    let a = solid(2, 2, [100, 100, 100, 255]);
+    let mut b = solid(2, 2, [100, 100, 100, 0]);
+    b.pixels[0] = 105;
+    b.pixels[4 + 2] = 94;
+
+    // one pixel off by 5, one by 6, alpha is ignored
+    assert_eq!(a.diff_ratio_tolerant(&b, 0), Some(0.5));
+    assert_eq!(a.diff_ratio_tolerant(&b, 4), Some(0.5));
+    assert_eq!(a.diff_ratio_tolerant(&b, 5), Some(0.25));
+    assert_eq!(a.diff_ratio_tolerant(&b, 6), Some(0.0));
+    assert_eq!(a.diff_ratio_tolerant(&solid(4, 1, [0; 4]), 0), None);
+    assert_eq!(solid(0, 0, [0; 4]).diff_ratio_tolerant(&solid(0, 0, [0; 4]), 0), Some(0.0));
+}
+
+// 4x3 white buffer with a 2x1 content block at 1, 1 inside a one pixel border
+fn bordered() -> RgbBuf {
+    let mut buf = solid(4, 3, [255, 255, 255, 255]);
+    let content = WindowRect {
+        x: 1,
+        y: 1,
+        width: 2,
+        height: 1,
+    };
+    buf.redact(content, RedactMode::Fill([0, 0, 10]));
+    buf
+}
+
+#[test]
+fn autocrop() {
+    let uniform = solid(3, 2, [255, 255, 255, 255]);
+    let cropped = uniform.autocrop([255, 255, 255], 0);
+    assert!(cropped.pixels_equal(&uniform));
+
+    let cropped = bordered().autocrop([255, 255, 255], 0);
+    assert!(cropped.pixels_equal(&solid(2, 1, [0, 0, 10, 255])));
+    // the border matches within tol, the content doesn't
+    let cropped = bordered().autocrop([250, 250, 250], 5);
+    assert_eq!((cropped.width, cropped.height), (2, 1));
+
+    let full = bordered();
+    let cropped = full.autocrop([1, 2, 3], 0);
+    assert!(cropped.pixels_equal(&full));
+}
+
+#[test]
+fn detect_content_rect() {
+    let white = [255, 255, 255];
+    let uniform = solid(3, 2, [255, 255, 255, 255]);
+    assert_eq!(uniform.detect_content_rect(white, 0), WindowRect::default());
+
+    let content = WindowRect {
+        x: 1,
+        y: 1,
+        width: 2,
+        height: 1,
+    };
+    assert_eq!(bordered().detect_content_rect(white, 0), content);
+    // the content is at most 255 away from white on any channel
+    assert_eq!(bordered().detect_content_rect(white, 254), content);
+    assert_eq!(bordered().detect_content_rect(white, 255), WindowRect::default());
+
+    let full = WindowRect {
+        x: 0,
+        y: 0,
+        width: 4,
+        height: 3,
+    };
+    assert_eq!(bordered().detect_content_rect([1, 2, 3], 0), full);
+}
+
+#[test]
+fn overlay_clipping() {
+    let reds = |b: &RgbBuf| b.pixels.chunks_exact(4).map(|p| p[0]).collect::<Vec<_>>();
+    // 2x2, red numbers the pixels
+    let top = RgbBuf {
+        pixels: [10, 20, 30, 40].iter().flat_map(|&r| [r, 0, 0, 255]).collect(),
+        width: 2,
+        height: 2,
+    };
+
+    // only the bottom right pixel of top lands on the canvas
+    let mut canvas = solid(3, 3, [0, 0, 0, 255]);
+    canvas.overlay(&top, -1, -1, 1.0);
+    assert_eq!(reds(&canvas), [40, 0, 0, 0, 0, 0, 0, 0, 0]);
+
+    // only the top left pixel, blended at half strength
+    let mut canvas = solid(3, 3, [0, 0, 0, 255]);
+    canvas.overlay(&top, 2, 2, 0.5);
+    assert_eq!(reds(&canvas), [0, 0, 0, 0, 0, 0, 0, 0, 5]);
+    assert_eq!(canvas.pixels[8 * 4 + 3], 255);
+
+    for (x, y) in [(3, 0), (0, 3), (-2, 0), (i32::MIN, i32::MIN), (i32::MAX, i32::MAX)] {
+        let mut canvas = solid(3, 3, [0, 0, 0, 255]);
+        canvas.overlay(&top, x, y, 1.0);
+        assert!(canvas.pixels_equal(&solid(3, 3, [0, 0, 0, 255])));
+    }
+}
+
+#[test]
+fn draw_text_clipping() {
+    let reds = |b: &RgbBuf| b.pixels.chunks_exact(4).map(|p| p[0]).collect::<Vec<_>>();
+    // '-' is the fourth row of all five glyph columns
+
+    // cut off on the left, the row lands on y 2
+    let mut canvas = solid(4, 4, [0, 0, 0, 255]);
+    canvas.draw_text("-", -2, -1, [255, 0, 0]);
+    #[rustfmt::skip]
+    assert_eq!(reds(&canvas), [
+        0, 0, 0, 0,
+        0, 0, 0, 0,
+        255, 255, 255, 0,
+        0, 0, 0, 0,
+    ]);
+
+    // cut off on the right, the second line is below the canvas
+    let mut canvas = solid(4, 4, [0, 0, 0, 255]);
+    canvas.draw_text("-\n-", 1, 0, [255, 0, 0]);
+    #[rustfmt::skip]
+    assert_eq!(reds(&canvas), [
+        0, 0, 0, 0,
+        0, 0, 0, 0,
+        0, 0, 0, 0,
+        0, 255, 255, 255,
+    ]);
+
+    for (x, y) in [(4, 0), (0, 4), (-6, 0), (0, -4), (i32::MIN, i32::MIN), (i32::MAX, i32::MAX)] {
+        let mut canvas = solid(4, 4, [0, 0, 0, 255]);
+        canvas.draw_text("-", x, y, [255, 0, 0]);
+        assert!(canvas.pixels_equal(&solid(4, 4, [0, 0, 0, 255])));
+    }
+}
+
+#[test]
+fn write_raw() {
+    // 2x2, pixel n is [n, 10 + n, 20 + n, 255]
+    let buf = RgbBuf {
+        pixels: (0..4).flat_map(|n| [n, 10 + n, 20 + n, 255]).collect(),
+        width: 2,
+        height: 2,
+    };
+    let raw = |format| {
+        let mut out = Vec::new();
+        buf.write_raw(&mut out, format).unwrap();
+        out
+    };
+
+    assert_eq!(raw(RawFormat::Rgba), buf.pixels);
+    assert_eq!(raw(RawFormat::Rgb), [0, 10, 20, 1, 11, 21, 2, 12, 22, 3, 13, 23]);
+    assert_eq!(raw(RawFormat::Bgr), [20, 10, 0, 21, 11, 1, 22, 12, 2, 23, 13, 3]);
+    assert_eq!(raw(RawFormat::BgrBottomUp), [22, 12, 2, 23, 13, 3, 20, 10, 0, 21, 11, 1]);
+    assert_eq!(
+        raw(RawFormat::BgraBottomUp),
+        [22, 12, 2, 255, 23, 13, 3, 255, 20, 10, 0, 255, 21, 11, 1, 255]
+    );
+}
+
+#[test]
+fn histogram() {
+    let buf = RgbBuf {
+        pixels: vec![0, 128, 255, 7, 0, 128, 10, 7, 5, 128, 255, 7],
+        width: 3,
+        height: 1,
+    };
+    let [r, g, b] = buf.histogram();
+    assert_eq!((r[0], r[5]), (2, 1));
+    assert_eq!(g[128], 3);
+    assert_eq!((b[255], b[10]), (2, 1));
+    // alpha isn't counted
+    assert_eq!([r, g, b].map(|c| c[7]), [0, 0, 0]);
+    assert!([r, g, b].iter().all(|c| c.iter().sum::<u32>() == 3));
+}
+
+#[test]
+fn dominant_color() {
+    // three dark grays share a bin with 4 buckets, the two reds another
+    let buf = RgbBuf {
+        pixels: [[10, 10, 10], [200, 0, 0], [20, 20, 20], [210, 0, 0], [30, 30, 30]]
+            .iter()
+            .flat_map(|&[r, g, b]| [r, g, b, 255])
+            .collect(),
+        width: 5,
+        height: 1,
+    };
+    assert_eq!(buf.dominant_color(4), [20, 20, 20]);
+    assert_eq!(solid(0, 0, [0; 4]).dominant_color(4), [0, 0, 0]);
+}
+
+#[test]
+fn content_hash() {
+    let buf = RgbBuf {
+        pixels: (0..24).collect(),
+        width: 3,
+        height: 2,
+    };
+    let same = RgbBuf {
+        pixels: (0..24).collect(),
+        width: 3,
+        height: 2,
+    };
+    assert_eq!(buf.content_hash(), same.content_hash());
+    // pinned, the hash doesn't depend on the platform
+    assert_eq!(buf.content_hash(), 10877674708720722545);
+
+    let mut changed = same;
+    changed.pixels[23] = 0;
+    assert_ne!(buf.content_hash(), changed.content_hash());
+    // same bytes, different dimensions
+    let transposed = RgbBuf {
+        pixels: (0..24).collect(),
+        width: 2,
+        height: 3,
+    };
+    assert_ne!(buf.content_hash(), transposed.content_hash());
+}
+
+#[test]
+fn merge_spans() {
+    use crate::capture::merge_spans;
+
+    // adjacent
+    assert_eq!(merge_spans([(0, 1), (1, 2), (2, 5)]), [(0, 5)]);
+    // overlapping, contained and out of order
+    assert_eq!(merge_spans([(4, 8), (0, 5), (5, 6)]), [(0, 8)]);
+    // disjoint
+    assert_eq!(merge_spans([(6, 7), (0, 2), (3, 4)]), [(0, 2), (3, 4), (6, 7)]);
+    // empty spans are dropped
+    assert_eq!(merge_spans([(3, 3), (1, 2)]), [(1, 2)]);
+    assert_eq!(merge_spans([]), []);
+}
+
+#[test]
+fn auto_chain_errors() {
+    use crate::capture::auto_chain;
+
+    let chain = [Using::PrintWindow, Using::WmPrint, Using::BitBlt];
+    let size = || WindowSize {
+        width: 1,
+        height: 1,
+    };
+    let blank = |b: &[u8]| b.iter().all(|&v| v == 0);
+
+    // PrintWindow and WM_PRINT fail, the window DC BitBlt is still reached
+    let mut tried = Vec::new();
+    let mut buffer = Vec::new();
+    let result = auto_chain(
+        &mut buffer,
+        &chain,
+        |buffer, using| {
+            tried.push(using);
+            match using {
+                Using::BitBlt => {
+                    *buffer = vec![1, 2, 3, 255];
+                    Ok(Some(size()))
+                }
+                _ => Err("failed"),
+            }
+        },
+        blank,
+        |_| Err("screen"),
+    );
+    assert!(matches!(result, Ok(Some(_))));
+    assert_eq!(tried, chain);
+
+    // failures and blank frames both move on, the fallback error is returned
+    let mut buffer = Vec::new();
+    let result = auto_chain(
+        &mut buffer,
+        &chain,
+        |buffer, using| match using {
+            Using::WmPrint => {
+                *buffer = vec![0; 4];
+                Ok(Some(size()))
+            }
+            _ => Err("failed"),
+        },
+        blank,
+        |_| Err("screen"),
+    );
+    assert!(matches!(result, Err("screen")));
+
+    let mut buffer = Vec::new();
+    let result = auto_chain(
+        &mut buffer,
+        &chain,
+        |_, _| Err("failed"),
+        blank,
+        |_| Ok(size()),
+    );
+    assert!(matches!(result, Ok(Some(_))));
+}
+
+#[test]
+fn render_scaled_zero_size() {
+    for (w, h) in [(0, 10), (10, 0), (-1, 10)] {
+        let result = capture_window_render_scaled(0, w, h);
+        assert!(matches!(result, Err(WSError::InvalidSize)));
+    }
+}
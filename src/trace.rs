@@ -0,0 +1,37 @@
+// Thin wrappers over tracing that expand to nothing without the tracing
+// feature, so the capture functions pay nothing for them by default.
+
+// Enters a debug span that lives until the end of the enclosing scope.
+// Without the feature the span is a NoSpan.
+macro_rules! trace_span {
+    ($($args:tt)*) => {{
+        #[cfg(feature = "tracing")]
+        let span = tracing::debug_span!($($args)*).entered();
+        #[cfg(not(feature = "tracing"))]
+        let span = $crate::trace::NoSpan;
+        span
+    }};
+}
+
+// stands in for the entered span without the feature, not () so binding it
+// isn't a unit let binding
+#[cfg(not(feature = "tracing"))]
+pub(crate) struct NoSpan;
+
+macro_rules! trace_record {
+    ($span:ident, $($field:literal = $value:expr),* $(,)?) => {
+        #[cfg(feature = "tracing")]
+        {
+            $($span.record($field, $value);)*
+        }
+    };
+}
+
+macro_rules! trace_event {
+    ($($args:tt)*) => {
+        #[cfg(feature = "tracing")]
+        tracing::trace!($($args)*);
+    };
+}
+
+pub(crate) use {trace_event, trace_record, trace_span};
@@ -1,15 +1,91 @@
 use std::ffi::OsString;
-use std::os::windows::ffi::OsStrExt;
-use windows::core::PCWSTR;
-use windows::Win32::Foundation::{BOOL, HWND, LPARAM};
+use std::mem::size_of;
+use std::os::windows::ffi::{OsStrExt, OsStringExt};
+use std::path::PathBuf;
+use std::sync::Once;
+use windows::core::{PCWSTR, PWSTR};
+use windows::Win32::Foundation::{CloseHandle, BOOL, E_ACCESSDENIED, HWND, LPARAM, RECT};
+use windows::Win32::Graphics::Dwm::{
+    DwmGetWindowAttribute, DWMWA_CLOAKED, DWMWA_EXTENDED_FRAME_BOUNDS,
+};
+use windows::Win32::System::Com::{
+    CoCreateInstance, CoInitializeEx, CoUninitialize, CLSCTX_ALL, COINIT_MULTITHREADED,
+};
+use windows::Win32::System::StationsAndDesktops::{
+    CloseDesktop, OpenInputDesktop, DESKTOP_CONTROL_FLAGS, DESKTOP_READOBJECTS,
+};
+use windows::Win32::System::Threading::{
+    OpenProcess, QueryFullProcessImageNameW, PROCESS_NAME_WIN32, PROCESS_QUERY_LIMITED_INFORMATION,
+};
+use windows::Win32::UI::HiDpi::{
+    GetDpiForWindow, SetProcessDpiAwareness, PROCESS_PER_MONITOR_DPI_AWARE,
+};
+use windows::Win32::UI::Shell::{IVirtualDesktopManager, VirtualDesktopManager};
 use windows::Win32::UI::WindowsAndMessaging::{
-    EnumWindows, FindWindowW, GetWindowTextLengthW, GetWindowTextW, IsWindowVisible,
+    EnumChildWindows, EnumWindows, FindWindowW, GetClassNameW, GetForegroundWindow,
+    GetSystemMetrics, GetWindow, GetWindowDisplayAffinity, GetWindowLongW, GetWindowTextLengthW,
+    GetWindowTextW, GetWindowThreadProcessId, IsIconic, IsWindowVisible, GWL_EXSTYLE, GW_OWNER,
+    SM_CXSCREEN, SM_CXVIRTUALSCREEN, SM_CYSCREEN, SM_CYVIRTUALSCREEN, SM_XVIRTUALSCREEN,
+    SM_YVIRTUALSCREEN, WDA_NONE, WS_EX_TOOLWINDOW,
 };
 
+#[cfg(feature = "uiautomation")]
+use std::mem::ManuallyDrop;
+#[cfg(feature = "uiautomation")]
+use windows::core::BSTR;
+#[cfg(feature = "uiautomation")]
+use windows::Win32::System::Com::{VARIANT, VARIANT_0, VARIANT_0_0, VARIANT_0_0_0, VT_BSTR};
+#[cfg(feature = "uiautomation")]
+use windows::Win32::UI::Accessibility::{
+    CUIAutomation, IUIAutomation, TreeScope_Descendants, UIA_AutomationIdPropertyId,
+};
+
+use crate::capture::WSError;
+use crate::geometry::WindowRect;
+use crate::wrappers::Rect;
+
+static DPI_AWARENESS: Once = Once::new();
+
+// The awareness can only be set once per process, repeated calls fail with
+// E_ACCESSDENIED. Once makes it a single call no matter how many threads
+// capture concurrently.
+pub(crate) fn set_dpi_awareness() {
+    DPI_AWARENESS.call_once(|| unsafe {
+        // win 8.1 temporary DPI aware
+        let _ = SetProcessDpiAwareness(PROCESS_PER_MONITOR_DPI_AWARE);
+        // for win 10
+        //SetThreadDpiAwarenessContext(DPI_AWARENESS_CONTEXT_PER_MONITOR_AWARE_V2);
+    });
+}
+
 #[derive(Debug)]
 pub struct HwndName {
     pub hwnd: isize,
     pub window_name: String,
+    // exact title, window_name replaces invalid UTF-16 with U+FFFD
+    pub window_name_os: OsString,
+    pub class_name: String,
+    pub process_id: u32,
+}
+
+// Window handle to pass between threads. HWNDs are process global and every
+// function taking one can be called from any thread. What is not shareable
+// are the GDI objects a capture creates (DCs, bitmaps): they live inside one
+// call, or inside a CaptureSession, which is why CaptureSession is not Send.
+// Buffers (RgbBuf, BgraBuf) are plain data and Send + Sync.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SendWindow(pub isize);
+
+impl SendWindow {
+    pub fn hwnd(self) -> isize {
+        self.0
+    }
+}
+
+impl From<isize> for SendWindow {
+    fn from(hwnd: isize) -> Self {
+        SendWindow(hwnd)
+    }
 }
 
 #[derive(Debug)]
@@ -36,13 +112,62 @@ pub fn find_window(window_name: &str) -> Result<isize, FWError> {
     }
 }
 
+// The default filter keeps every visible window with a title
+#[derive(Debug, Default, Clone, Copy)]
+pub struct WindowFilter {
+    pub exclude_tool_windows: bool,
+    pub exclude_owned: bool,
+    pub exclude_cloaked: bool,
+}
+
+struct WindowListParams<'a> {
+    filter: WindowFilter,
+    predicate: &'a dyn Fn(&HwndName) -> bool,
+    windows: Vec<HwndName>,
+    // window rects in the same order as windows, only collected when Some
+    bounds: Option<Vec<WindowRect>>,
+}
+
+// cloaked windows (hidden UWP hosts, apps on another virtual desktop) pass
+// IsWindowVisible but capture as black
+pub(crate) fn is_cloaked(hwnd: HWND) -> bool {
+    let mut cloaked = 0u32;
+    unsafe {
+        DwmGetWindowAttribute(
+            hwnd,
+            DWMWA_CLOAKED,
+            &mut cloaked as *mut u32 as *mut core::ffi::c_void,
+            size_of::<u32>() as u32,
+        )
+        .is_ok()
+            && cloaked != 0
+    }
+}
+
+unsafe fn is_filtered_out(hwnd: HWND, filter: &WindowFilter) -> bool {
+    // the styles fit in 32 bits, GetWindowLongPtrW only exists on 64-bit
+    if filter.exclude_tool_windows
+        && GetWindowLongW(hwnd, GWL_EXSTYLE) as u32 & WS_EX_TOOLWINDOW.0 != 0
+    {
+        return true;
+    }
+    if filter.exclude_owned && GetWindow(hwnd, GW_OWNER) != HWND(0) {
+        return true;
+    }
+    filter.exclude_cloaked && is_cloaked(hwnd)
+}
+
 unsafe extern "system" fn wl_callback(hwnd: HWND, lparam: LPARAM) -> BOOL {
-    let vec = lparam.0 as *mut Vec<HwndName>;
+    let params = lparam.0 as *mut WindowListParams<'_>;
 
     if IsWindowVisible(hwnd) == false {
         return BOOL::from(true);
     }
 
+    if is_filtered_out(hwnd, &(*params).filter) {
+        return BOOL::from(true);
+    }
+
     let gwtl = GetWindowTextLengthW(hwnd);
     if gwtl == 0 {
         return BOOL::from(true);
@@ -62,10 +187,33 @@ unsafe extern "system" fn wl_callback(hwnd: HWND, lparam: LPARAM) -> BOOL {
 
     let name = String::from_utf16_lossy(name_buf);
 
-    (*vec).push(HwndName {
+    // 256 is the maximum length of a class name
+    let mut class_buf = [0u16; 256];
+    let gcn = GetClassNameW(hwnd, &mut class_buf);
+
+    let hwnd_name = HwndName {
         hwnd: hwnd.0,
         window_name: name,
-    });
+        window_name_os: OsString::from_wide(name_buf),
+        class_name: String::from_utf16_lossy(&class_buf[..gcn.max(0) as usize]),
+        process_id: get_window_thread_process_id(hwnd.0).1,
+    };
+
+    if !((*params).predicate)(&hwnd_name) {
+        return BOOL::from(true);
+    }
+    if let Some(bounds) = &mut (*params).bounds {
+        match Rect::get_window_rect(hwnd) {
+            Ok(rect) => bounds.push(WindowRect {
+                x: rect.left,
+                y: rect.top,
+                width: rect.width,
+                height: rect.height,
+            }),
+            Err(_) => return BOOL::from(true),
+        }
+    }
+    (*params).windows.push(hwnd_name);
 
     BOOL::from(true)
 }
@@ -73,18 +221,401 @@ unsafe extern "system" fn wl_callback(hwnd: HWND, lparam: LPARAM) -> BOOL {
 #[derive(Debug)]
 pub enum WLError {
     EnumWindowsError,
+    // no UI Automation element with the requested AutomationId
+    ElementNotFound,
+    // neither the element nor any of its ancestors has a native window
+    NoNativeWindow,
+    Windows(windows::core::Error),
 }
 
+impl From<windows::core::Error> for WLError {
+    fn from(e: windows::core::Error) -> Self {
+        WLError::Windows(e)
+    }
+}
+
+// cloaked windows are skipped, they can't be captured anyway
 pub fn window_list() -> Result<Vec<HwndName>, WLError> {
-    let mut hwnd_name = Vec::new();
+    window_list_ex(WindowFilter {
+        exclude_cloaked: true,
+        ..Default::default()
+    })
+}
+
+pub fn window_list_ex(filter: WindowFilter) -> Result<Vec<HwndName>, WLError> {
+    enum_windows(filter, &|_| true)
+}
+
+// Only windows accepted by predicate are collected, the predicate runs inside
+// the enumeration so rejected windows are never stored
+pub fn window_list_filtered<F>(predicate: F) -> Result<Vec<HwndName>, WLError>
+where
+    F: Fn(&HwndName) -> bool,
+{
+    enum_windows(
+        WindowFilter {
+            exclude_cloaked: true,
+            ..Default::default()
+        },
+        &predicate,
+    )
+}
+
+// window_list with each window's GetWindowRect, measured during the
+// enumeration. Windows whose rect can't be read are left out.
+pub fn window_list_with_bounds() -> Result<Vec<(HwndName, WindowRect)>, WLError> {
+    let mut params = WindowListParams {
+        filter: WindowFilter {
+            exclude_cloaked: true,
+            ..Default::default()
+        },
+        predicate: &|_| true,
+        windows: Vec::new(),
+        bounds: Some(Vec::new()),
+    };
     unsafe {
         let ew = EnumWindows(
             Some(wl_callback),
-            LPARAM(&mut hwnd_name as *mut Vec<HwndName> as isize),
+            LPARAM(&mut params as *mut WindowListParams as isize),
         );
         if ew == false {
             return Err(WLError::EnumWindowsError);
         }
     }
-    Ok(hwnd_name)
+    let bounds = params.bounds.unwrap_or_default();
+    Ok(params.windows.into_iter().zip(bounds).collect())
+}
+
+// The most prominent window accepted by pred: the topmost one that isn't
+// minimized, or the topmost minimized one if that's all there is. EnumWindows
+// goes through top-level windows in z-order, front to back.
+pub fn find_best_window<F>(pred: F) -> Option<HwndName>
+where
+    F: Fn(&HwndName) -> bool,
+{
+    let windows = window_list_filtered(pred).ok()?;
+    let minimized = |w: &HwndName| unsafe { IsIconic(HWND(w.hwnd)).as_bool() };
+    match windows.iter().position(|w| !minimized(w)) {
+        Some(i) => windows.into_iter().nth(i),
+        None => windows.into_iter().next(),
+    }
+}
+
+// the window accepted by pred with the largest window rect, the topmost one
+// among equally large windows
+pub fn find_largest_window<F>(pred: F) -> Option<HwndName>
+where
+    F: Fn(&HwndName) -> bool,
+{
+    window_list_with_bounds()
+        .ok()?
+        .into_iter()
+        .filter(|(w, _)| pred(w))
+        .rev()
+        .max_by_key(|(_, rect)| rect.area())
+        .map(|(w, _)| w)
+}
+
+// Visible, titled top-level windows of the process. With include_children
+// the descendants of those windows are added after them, filtered the same
+// way, for apps that render their content into a child window.
+pub fn windows_for_pid(pid: u32, include_children: bool) -> Result<Vec<HwndName>, WLError> {
+    let filter = WindowFilter {
+        exclude_cloaked: true,
+        ..Default::default()
+    };
+    let predicate = |w: &HwndName| w.process_id == pid;
+    let mut windows = enum_windows(filter, &predicate)?;
+    if include_children {
+        let mut params = WindowListParams {
+            filter,
+            predicate: &predicate,
+            windows: Vec::new(),
+            bounds: None,
+        };
+        for window in &windows {
+            // the return value of EnumChildWindows is not used
+            unsafe {
+                EnumChildWindows(
+                    HWND(window.hwnd),
+                    Some(wl_callback),
+                    LPARAM(&mut params as *mut WindowListParams as isize),
+                );
+            }
+        }
+        windows.append(&mut params.windows);
+    }
+    Ok(windows)
+}
+
+fn enum_windows(
+    filter: WindowFilter,
+    predicate: &dyn Fn(&HwndName) -> bool,
+) -> Result<Vec<HwndName>, WLError> {
+    let mut params = WindowListParams {
+        filter,
+        predicate,
+        windows: Vec::new(),
+        bounds: None,
+    };
+    unsafe {
+        let ew = EnumWindows(
+            Some(wl_callback),
+            LPARAM(&mut params as *mut WindowListParams as isize),
+        );
+        if ew == false {
+            return Err(WLError::EnumWindowsError);
+        }
+    }
+    Ok(params.windows)
+}
+
+// bounding rectangle of all monitors, the origin is negative when a monitor
+// is placed left of or above the primary one
+pub fn virtual_screen_bounds() -> WindowRect {
+    set_dpi_awareness();
+    unsafe {
+        WindowRect {
+            x: GetSystemMetrics(SM_XVIRTUALSCREEN),
+            y: GetSystemMetrics(SM_YVIRTUALSCREEN),
+            width: GetSystemMetrics(SM_CXVIRTUALSCREEN),
+            height: GetSystemMetrics(SM_CYVIRTUALSCREEN),
+        }
+    }
+}
+
+// True while the UAC prompt, lock screen or Ctrl+Alt+Del screen is shown:
+// there is no foreground window on our desktop and the input desktop can't
+// be opened. Captures then fail or come back black.
+pub fn is_secure_desktop_active() -> bool {
+    unsafe {
+        if GetForegroundWindow() != HWND(0) {
+            return false;
+        }
+        match OpenInputDesktop(DESKTOP_CONTROL_FLAGS(0), false, DESKTOP_READOBJECTS) {
+            Ok(desktop) => {
+                CloseDesktop(desktop);
+                false
+            }
+            Err(_) => true,
+        }
+    }
+}
+
+// False if the window lives on another virtual desktop, it is cloaked there
+// and captures as black
+pub fn is_window_on_current_desktop(hwnd: isize) -> Result<bool, WSError> {
+    unsafe {
+        // fails with RPC_E_CHANGED_MODE on threads that are already STA,
+        // COM is usable then too but must not be uninitialized by us
+        let initialized = CoInitializeEx(None, COINIT_MULTITHREADED).is_ok();
+        let result = (|| {
+            let manager: IVirtualDesktopManager =
+                CoCreateInstance(&VirtualDesktopManager, None, CLSCTX_ALL)?;
+            manager.IsWindowOnCurrentVirtualDesktop(HWND(hwnd))
+        })();
+        if initialized {
+            CoUninitialize();
+        }
+        Ok(result?.as_bool())
+    }
+}
+
+// True if the window opted out of screen capture with
+// SetWindowDisplayAffinity: WDA_MONITOR windows capture as black,
+// WDA_EXCLUDEFROMCAPTURE windows are left out of display captures entirely
+// (the desktop behind them shows instead).
+pub fn is_capture_protected(hwnd: isize) -> bool {
+    let mut affinity = 0u32;
+    unsafe {
+        GetWindowDisplayAffinity(HWND(hwnd), &mut affinity).as_bool() && affinity != WDA_NONE.0
+    }
+}
+
+// Window rect without the invisible resize borders and drop shadow that
+// GetWindowRect includes since Vista, i.e. what is visible on screen
+pub fn get_visible_window_rect(hwnd: isize) -> Result<WindowRect, WSError> {
+    set_dpi_awareness();
+    let mut rect = RECT::default();
+    unsafe {
+        DwmGetWindowAttribute(
+            HWND(hwnd),
+            DWMWA_EXTENDED_FRAME_BOUNDS,
+            &mut rect as *mut RECT as *mut core::ffi::c_void,
+            size_of::<RECT>() as u32,
+        )?;
+    }
+    Ok(rect.into())
+}
+
+// size of the primary monitor in physical pixels, not of the virtual screen
+pub fn primary_resolution() -> (u32, u32) {
+    set_dpi_awareness();
+    unsafe {
+        (
+            GetSystemMetrics(SM_CXSCREEN).max(0) as u32,
+            GetSystemMetrics(SM_CYSCREEN).max(0) as u32,
+        )
+    }
+}
+
+// 96 is 100% scaling
+pub fn get_dpi_for_window(hwnd: isize) -> Result<u32, WSError> {
+    unsafe {
+        match GetDpiForWindow(HWND(hwnd)) {
+            0 => Err(WSError::GetDpiForWindowIsZero),
+            dpi => Ok(dpi),
+        }
+    }
+}
+
+// Window rect in device independent pixels (1/96 inch), the coordinates the
+// app itself sees when it isn't DPI aware. Values are rounded to the nearest
+// integer.
+pub fn get_window_bounds_in_dips(hwnd: isize) -> Result<WindowRect, WSError> {
+    set_dpi_awareness();
+    let rect = Rect::get_window_rect(HWND(hwnd))?;
+    let scale = get_dpi_for_window(hwnd)? as f64 / 96.0;
+    let dips = |v: i32| (v as f64 / scale).round() as i32;
+    Ok(WindowRect {
+        x: dips(rect.left),
+        y: dips(rect.top),
+        width: dips(rect.width),
+        height: dips(rect.height),
+    })
+}
+
+// empty for windows without a title
+pub(crate) fn get_window_text(hwnd: HWND) -> String {
+    unsafe {
+        let len = GetWindowTextLengthW(hwnd);
+        if len <= 0 {
+            return String::new();
+        }
+        let mut buf = vec![0u16; len as usize + 1];
+        let copied = GetWindowTextW(hwnd, &mut buf);
+        String::from_utf16_lossy(&buf[..copied.max(0) as usize])
+    }
+}
+
+pub(crate) fn get_class_name(hwnd: HWND) -> String {
+    // 256 is the maximum length of a class name
+    let mut buf = [0u16; 256];
+    let len = unsafe { GetClassNameW(hwnd, &mut buf) };
+    String::from_utf16_lossy(&buf[..len.max(0) as usize])
+}
+
+// HwndName of any window, window_name is empty if it has no title
+pub(crate) fn hwnd_name(hwnd: HWND) -> HwndName {
+    let mut name_buf = vec![0u16; unsafe { GetWindowTextLengthW(hwnd) }.max(0) as usize + 1];
+    let len = unsafe { GetWindowTextW(hwnd, &mut name_buf) }.max(0) as usize;
+    HwndName {
+        hwnd: hwnd.0,
+        window_name: String::from_utf16_lossy(&name_buf[..len]),
+        window_name_os: OsString::from_wide(&name_buf[..len]),
+        class_name: get_class_name(hwnd),
+        process_id: get_window_thread_process_id(hwnd.0).1,
+    }
+}
+
+// (thread_id, process_id), both 0 if hwnd is not a window
+pub fn get_window_thread_process_id(hwnd: isize) -> (u32, u32) {
+    let mut process_id = 0;
+    let thread_id = unsafe { GetWindowThreadProcessId(HWND(hwnd), Some(&mut process_id)) };
+    (thread_id, process_id)
+}
+
+pub fn get_window_process_path(hwnd: isize) -> Result<PathBuf, WSError> {
+    let (_, process_id) = get_window_thread_process_id(hwnd);
+    if process_id == 0 {
+        return Err(WSError::ProcessNotFound);
+    }
+    unsafe {
+        // limited information is enough for the image name and is granted
+        // for more processes than PROCESS_QUERY_INFORMATION
+        let process =
+            OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, false, process_id).map_err(|e| {
+                if e.code() == E_ACCESSDENIED {
+                    WSError::ProcessAccessDenied
+                } else {
+                    WSError::Windows(e)
+                }
+            })?;
+
+        let mut path = vec![0u16; 1024];
+        let mut len = path.len() as u32;
+        let ok = QueryFullProcessImageNameW(
+            process,
+            PROCESS_NAME_WIN32,
+            PWSTR(path.as_mut_ptr()),
+            &mut len,
+        );
+        let err = windows::core::Error::from_win32();
+        CloseHandle(process);
+        if ok == false {
+            if err.code() == E_ACCESSDENIED {
+                return Err(WSError::ProcessAccessDenied);
+            }
+            return Err(WSError::Windows(err));
+        }
+        Ok(PathBuf::from(OsString::from_wide(&path[..len as usize])))
+    }
+}
+
+// Finds the UI Automation element whose AutomationId is id anywhere below
+// the desktop and returns the window hosting it. Elements without a native
+// window of their own (most WPF and UWP controls) resolve to the closest
+// ancestor that has one. COM is initialized as multithreaded for the call if
+// the thread hasn't initialized it yet, an existing apartment is used as is.
+#[cfg(feature = "uiautomation")]
+pub fn find_window_by_automation_id(id: &str) -> Result<isize, WLError> {
+    unsafe {
+        // see is_window_on_current_desktop
+        let initialized = CoInitializeEx(None, COINIT_MULTITHREADED).is_ok();
+        let result = (|| {
+            let automation: IUIAutomation = CoCreateInstance(&CUIAutomation, None, CLSCTX_ALL)?;
+            // the VARIANT borrows the string, bstr frees it when it goes out
+            // of scope
+            let bstr = BSTR::from(id);
+            let value = VARIANT {
+                Anonymous: VARIANT_0 {
+                    Anonymous: ManuallyDrop::new(VARIANT_0_0 {
+                        vt: VT_BSTR,
+                        Anonymous: VARIANT_0_0_0 {
+                            bstrVal: ManuallyDrop::new(std::mem::transmute_copy(&bstr)),
+                        },
+                        ..Default::default()
+                    }),
+                },
+            };
+            let condition =
+                automation.CreatePropertyCondition(UIA_AutomationIdPropertyId, value)?;
+
+            // a null element comes back as an error with a success code
+            let mut element = match automation
+                .GetRootElement()?
+                .FindFirst(TreeScope_Descendants, &condition)
+            {
+                Ok(element) => element,
+                Err(e) if e.code().is_ok() => return Err(WLError::ElementNotFound),
+                Err(e) => return Err(e.into()),
+            };
+            let walker = automation.ControlViewWalker()?;
+            loop {
+                let hwnd = element.CurrentNativeWindowHandle()?;
+                if hwnd.0 != 0 {
+                    return Ok(hwnd.0);
+                }
+                element = match walker.GetParentElement(&element) {
+                    Ok(parent) => parent,
+                    Err(e) if e.code().is_ok() => return Err(WLError::NoNativeWindow),
+                    Err(e) => return Err(e.into()),
+                };
+            }
+        })();
+        if initialized {
+            CoUninitialize();
+        }
+        result
+    }
 }
@@ -0,0 +1,240 @@
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{sync_channel, TrySendError};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crate::capture::WSError;
+use crate::options::CaptureOptions;
+use crate::session::{CaptureFrame, CaptureSession};
+
+// frames captured but not yet written to ffmpeg
+const PIPE_FRAMES: usize = 4;
+
+// What happens to a frame when PIPE_FRAMES are already waiting for ffmpeg
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FramePolicy {
+    // the capture loop waits, the video stays complete but the capture rate
+    // drops to what ffmpeg manages
+    #[default]
+    Block,
+    // the frame is skipped, the capture rate is kept
+    Drop,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RecordingStats {
+    pub frames: u64,
+    pub dropped: u64,
+}
+
+// Records a window to a video file by piping raw BGRA frames from a
+// CaptureSession into an ffmpeg process. Every frame is padded (with black)
+// or cropped to the size of the first one, ffmpeg needs a fixed frame size.
+pub struct VideoRecorder {
+    output: PathBuf,
+    ffmpeg: PathBuf,
+    codec: String,
+    fps: u32,
+    policy: FramePolicy,
+    options: CaptureOptions,
+}
+
+impl VideoRecorder {
+    // the container follows the extension of output, e.g. .mp4
+    pub fn new<P: Into<PathBuf>>(output: P) -> Self {
+        VideoRecorder {
+            output: output.into(),
+            ffmpeg: PathBuf::from("ffmpeg"),
+            codec: String::from("libx264"),
+            fps: 30,
+            policy: FramePolicy::Block,
+            options: CaptureOptions::default(),
+        }
+    }
+
+    // looked up in PATH unless it is a path
+    pub fn ffmpeg_path<P: Into<PathBuf>>(mut self, ffmpeg: P) -> Self {
+        self.ffmpeg = ffmpeg.into();
+        self
+    }
+
+    // passed to ffmpeg as -c:v
+    pub fn codec(mut self, codec: &str) -> Self {
+        self.codec = codec.to_string();
+        self
+    }
+
+    pub fn fps(mut self, fps: u32) -> Self {
+        self.fps = fps.max(1);
+        self
+    }
+
+    pub fn frame_policy(mut self, policy: FramePolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    // see CaptureSession, crop settings are ignored
+    pub fn capture_options(mut self, options: CaptureOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    // Captures the first frame and starts ffmpeg, recording continues on a
+    // background thread until Recording::stop. Fails with FfmpegSpawn if
+    // ffmpeg can't be started.
+    pub fn start(self, hwnd: isize) -> Result<Recording, WSError> {
+        let stop = Arc::new(AtomicBool::new(false));
+        let (started_tx, started_rx) = sync_channel(1);
+        let thread_stop = stop.clone();
+        let thread = thread::spawn(move || {
+            // CaptureSession isn't Send, it lives on this thread only
+            let mut session = match CaptureSession::with_options(hwnd, self.options) {
+                Ok(session) => session,
+                Err(e) => {
+                    let _ = started_tx.send(Err(e));
+                    return Ok(RecordingStats::default());
+                }
+            };
+            // only sizes the video, recording starts with the next frame
+            let first = match session.capture() {
+                Ok(frame) => (frame.width(), frame.height()),
+                Err(e) => {
+                    let _ = started_tx.send(Err(e));
+                    return Ok(RecordingStats::default());
+                }
+            };
+            let child = Command::new(&self.ffmpeg)
+                .args([
+                    "-y",
+                    "-loglevel",
+                    "error",
+                    "-f",
+                    "rawvideo",
+                    "-pix_fmt",
+                    "bgra",
+                ])
+                .args(["-s", &format!("{}x{}", first.0, first.1)])
+                .args(["-r", &self.fps.to_string(), "-i", "-"])
+                .args(["-c:v", &self.codec, "-pix_fmt", "yuv420p"])
+                // yuv420p needs even dimensions
+                .args(["-vf", "pad=ceil(iw/2)*2:ceil(ih/2)*2"])
+                .arg(&self.output)
+                .stdin(Stdio::piped())
+                .spawn();
+            let mut child = match child {
+                Ok(child) => child,
+                Err(e) => {
+                    let _ = started_tx.send(Err(WSError::FfmpegSpawn(e)));
+                    return Ok(RecordingStats::default());
+                }
+            };
+            let _ = started_tx.send(Ok(()));
+
+            let mut stdin = child.stdin.take().unwrap();
+            let (frame_tx, frame_rx) = sync_channel::<Vec<u8>>(PIPE_FRAMES);
+            let writer = thread::spawn(move || {
+                for frame in frame_rx {
+                    stdin.write_all(&frame)?;
+                }
+                // closing stdin ends the input, ffmpeg finalizes the file
+                Ok::<_, std::io::Error>(())
+            });
+
+            let interval = Duration::from_secs(1) / self.fps;
+            let mut stats = RecordingStats::default();
+            let mut next = Instant::now();
+            let mut result = Ok(());
+            while !thread_stop.load(Ordering::Relaxed) {
+                let frame = match session.capture() {
+                    Ok(frame) => fit_frame(&frame, first),
+                    Err(e) => {
+                        result = Err(e);
+                        break;
+                    }
+                };
+                match self.policy {
+                    // the writer stopped, its error is reported below
+                    FramePolicy::Block => match frame_tx.send(frame) {
+                        Ok(()) => stats.frames += 1,
+                        Err(_) => break,
+                    },
+                    FramePolicy::Drop => match frame_tx.try_send(frame) {
+                        Ok(()) => stats.frames += 1,
+                        Err(TrySendError::Full(_)) => stats.dropped += 1,
+                        Err(TrySendError::Disconnected(_)) => break,
+                    },
+                }
+                next += interval;
+                match next.checked_duration_since(Instant::now()) {
+                    Some(wait) => thread::sleep(wait),
+                    // running late, don't try to catch up with a burst of frames
+                    None => next = Instant::now(),
+                }
+            }
+            drop(frame_tx);
+
+            let written = writer.join().unwrap();
+            let status = child.wait()?;
+            result?;
+            // an ffmpeg that exited early shows up as a broken pipe in the
+            // writer, the exit code says why
+            if !status.success() {
+                return Err(WSError::FfmpegFailed(status.code()));
+            }
+            written?;
+            Ok(stats)
+        });
+
+        match started_rx.recv() {
+            Ok(Ok(())) => Ok(Recording {
+                stop,
+                thread: Some(thread),
+            }),
+            Ok(Err(e)) => Err(e),
+            // the thread panicked before it reported back
+            Err(_) => std::panic::resume_unwind(thread.join().unwrap_err()),
+        }
+    }
+}
+
+// A running VideoRecorder. Dropping it stops the recording too, but only
+// stop reports whether the video was written.
+pub struct Recording {
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<Result<RecordingStats, WSError>>>,
+}
+
+impl Recording {
+    // waits until ffmpeg has finalized the file
+    pub fn stop(mut self) -> Result<RecordingStats, WSError> {
+        self.stop.store(true, Ordering::Relaxed);
+        self.thread.take().unwrap().join().unwrap()
+    }
+}
+
+impl Drop for Recording {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+// copies the frame into a tightly packed width x height BGRA buffer
+fn fit_frame(frame: &CaptureFrame, (width, height): (u32, u32)) -> Vec<u8> {
+    let row_len = width as usize * 4;
+    let mut pixels = [0, 0, 0, 255].repeat((width * height) as usize);
+    let copy_len = row_len.min(frame.width() as usize * 4);
+    let stride = frame.stride() as usize;
+    for y in 0..height.min(frame.height()) as usize {
+        pixels[y * row_len..y * row_len + copy_len]
+            .copy_from_slice(&frame.as_slice()[y * stride..y * stride + copy_len]);
+    }
+    pixels
+}
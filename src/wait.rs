@@ -0,0 +1,64 @@
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::capture::{capture_region, get_pixel_color, RgbBuf, WSError};
+use crate::geometry::WindowRect;
+
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+// Polls the screen pixel at (x, y) until every channel is within tolerance
+// of expected, or fails with WSError::Timeout
+pub fn wait_for_pixel(
+    x: i32,
+    y: i32,
+    expected: [u8; 3],
+    tolerance: u8,
+    timeout: Duration,
+) -> Result<(), WSError> {
+    let deadline = Instant::now() + timeout;
+    loop {
+        let color = get_pixel_color(x, y)?;
+        if color
+            .iter()
+            .zip(expected)
+            .all(|(&c, e)| c.abs_diff(e) <= tolerance)
+        {
+            return Ok(());
+        }
+        if Instant::now() >= deadline {
+            return Err(WSError::Timeout);
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+}
+
+pub fn wait_for_region_change(
+    rect: WindowRect,
+    threshold: f32,
+    timeout: Duration,
+) -> Result<RgbBuf, WSError> {
+    wait_for_region_change_ex(rect, threshold, timeout, Duration::from_millis(100))
+}
+
+// Captures rect (virtual screen coordinates) and re-captures it every
+// interval until more than threshold (0.0..=1.0) of its pixels differ from
+// the first capture, then returns the new frame. Fails with WSError::Timeout.
+pub fn wait_for_region_change_ex(
+    rect: WindowRect,
+    threshold: f32,
+    timeout: Duration,
+    interval: Duration,
+) -> Result<RgbBuf, WSError> {
+    let deadline = Instant::now() + timeout;
+    let initial = capture_region(rect)?.buf;
+    loop {
+        thread::sleep(interval);
+        let current = capture_region(rect)?.buf;
+        if current.changed_ratio(&initial) > threshold {
+            return Ok(current);
+        }
+        if Instant::now() >= deadline {
+            return Err(WSError::Timeout);
+        }
+    }
+}
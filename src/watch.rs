@@ -0,0 +1,128 @@
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::thread;
+use windows::Win32::Foundation::HWND;
+use windows::Win32::UI::Accessibility::{SetWinEventHook, UnhookWinEvent, HWINEVENTHOOK};
+use windows::Win32::UI::WindowsAndMessaging::{
+    DispatchMessageW, GetAncestor, GetMessageW, IsWindowVisible, PostQuitMessage, TranslateMessage,
+    CHILDID_SELF, EVENT_OBJECT_CREATE, EVENT_OBJECT_DESTROY, EVENT_OBJECT_NAMECHANGE,
+    EVENT_OBJECT_SHOW, GA_ROOT, MSG, OBJID_WINDOW, WINEVENT_OUTOFCONTEXT, WINEVENT_SKIPOWNPROCESS,
+};
+
+use crate::utils::{get_window_text, hwnd_name, HwndName};
+
+#[derive(Debug)]
+pub enum WindowEvent {
+    // a top-level window became visible
+    Opened(HwndName),
+    // a window previously reported as Opened was destroyed
+    Closed(isize),
+    // title of a top-level window changed, also for windows that were
+    // already open when watching started
+    TitleChanged { hwnd: isize, new_title: String },
+}
+
+struct WatchState {
+    sender: Sender<WindowEvent>,
+    opened: HashSet<isize>,
+}
+
+thread_local! {
+    // WinEvent callbacks carry no user data, the state lives on the hook thread
+    static STATE: RefCell<Option<WatchState>> = const { RefCell::new(None) };
+}
+
+unsafe extern "system" fn we_callback(
+    _hook: HWINEVENTHOOK,
+    event: u32,
+    hwnd: HWND,
+    id_object: i32,
+    id_child: i32,
+    _event_thread: u32,
+    _event_time: u32,
+) {
+    // only events about the window itself, not its scroll bars, caret etc.
+    if id_object != OBJID_WINDOW.0 || id_child != CHILDID_SELF as i32 {
+        return;
+    }
+    STATE.with(|state| {
+        let mut state = state.borrow_mut();
+        let Some(state) = state.as_mut() else {
+            return;
+        };
+        let event = match event {
+            EVENT_OBJECT_SHOW
+                if GetAncestor(hwnd, GA_ROOT) == hwnd
+                    && IsWindowVisible(hwnd).as_bool()
+                    && state.opened.insert(hwnd.0) =>
+            {
+                WindowEvent::Opened(hwnd_name(hwnd))
+            }
+            EVENT_OBJECT_DESTROY if state.opened.remove(&hwnd.0) => WindowEvent::Closed(hwnd.0),
+            EVENT_OBJECT_NAMECHANGE if GetAncestor(hwnd, GA_ROOT) == hwnd => {
+                WindowEvent::TitleChanged {
+                    hwnd: hwnd.0,
+                    new_title: get_window_text(hwnd),
+                }
+            }
+            _ => return,
+        };
+        // the receiver was dropped, stop the message loop
+        if state.sender.send(event).is_err() {
+            PostQuitMessage(0);
+        }
+    });
+}
+
+// Reports windows opening, closing and changing their title from a
+// background thread without polling window_list. The thread unhooks and
+// exits with the first event after the receiver is dropped.
+pub fn watch_windows() -> Receiver<WindowEvent> {
+    let (sender, receiver) = channel();
+    thread::spawn(move || {
+        STATE.with(|state| {
+            *state.borrow_mut() = Some(WatchState {
+                sender,
+                opened: HashSet::new(),
+            })
+        });
+        unsafe {
+            // CREATE, DESTROY and SHOW are consecutive event ids
+            let hook = SetWinEventHook(
+                EVENT_OBJECT_CREATE,
+                EVENT_OBJECT_SHOW,
+                None,
+                Some(we_callback),
+                0,
+                0,
+                WINEVENT_OUTOFCONTEXT | WINEVENT_SKIPOWNPROCESS,
+            );
+            // NAMECHANGE is far from the others, a range covering all of
+            // them would also deliver every focus and location change
+            let name_hook = SetWinEventHook(
+                EVENT_OBJECT_NAMECHANGE,
+                EVENT_OBJECT_NAMECHANGE,
+                None,
+                Some(we_callback),
+                0,
+                0,
+                WINEVENT_OUTOFCONTEXT | WINEVENT_SKIPOWNPROCESS,
+            );
+            if hook.is_invalid() || name_hook.is_invalid() {
+                UnhookWinEvent(hook);
+                UnhookWinEvent(name_hook);
+                return;
+            }
+            // out of context hooks are delivered through this thread's queue
+            let mut msg = MSG::default();
+            while GetMessageW(&mut msg, None, 0, 0).0 > 0 {
+                TranslateMessage(&msg);
+                DispatchMessageW(&msg);
+            }
+            UnhookWinEvent(hook);
+            UnhookWinEvent(name_hook);
+        }
+    });
+    receiver
+}
@@ -1,38 +1,73 @@
+use std::marker::PhantomData;
+use std::mem;
+
 use windows::{
     core::{Error, IntoParam},
     Win32::{
-        Foundation::{HWND, RECT},
+        Foundation::{ERROR_INCORRECT_SIZE, HWND, RECT},
         Graphics::Gdi::{
             CreateCompatibleBitmap, CreateCompatibleDC, CreatedHDC, DeleteDC, DeleteObject, GetDC,
-            ReleaseDC, HBITMAP, HDC,
+            GetWindowDC, ReleaseDC, SelectObject, HBITMAP, HDC, HGDIOBJ,
         },
         UI::WindowsAndMessaging::{GetClientRect, GetWindowRect},
     },
 };
 
-#[derive(Clone)]
-pub(crate) struct Hdc {
+use crate::geometry::WindowRect;
+
+// DC of a window (or the screen for HWND(0)), released on drop
+pub struct Hdc {
     pub(crate) hdc: HDC,
+    // ReleaseDC needs the window the DC was retrieved for
+    hwnd: HWND,
 }
 
 impl Hdc {
-    pub(crate) fn get_dc<P0>(hwnd: P0) -> Result<Hdc, Error>
+    pub fn get_dc<P0>(hwnd: P0) -> Result<Hdc, Error>
     where
         P0: Into<HWND>,
     {
+        let hwnd = hwnd.into();
         unsafe {
-            match GetDC(hwnd.into()) {
+            match GetDC(hwnd) {
                 e if e.is_invalid() => Err(Error::from_win32()),
-                hdc => Ok(Hdc { hdc }),
+                hdc => Ok(Hdc { hdc, hwnd }),
             }
         }
     }
+
+    // the whole window including the non-client area, origin at the top
+    // left corner of the window rect
+    pub fn get_window_dc<P0>(hwnd: P0) -> Result<Hdc, Error>
+    where
+        P0: Into<HWND>,
+    {
+        let hwnd = hwnd.into();
+        unsafe {
+            match GetWindowDC(hwnd) {
+                e if e.is_invalid() => Err(Error::from_win32()),
+                hdc => Ok(Hdc { hdc, hwnd }),
+            }
+        }
+    }
+
+    pub fn handle(&self) -> HDC {
+        self.hdc
+    }
+
+    // Gives up ownership of the DC without releasing it, the caller has to
+    // ReleaseDC it with the window it was retrieved for
+    pub fn into_raw(self) -> HDC {
+        let hdc = self.hdc;
+        mem::forget(self);
+        hdc
+    }
 }
 
 impl Drop for Hdc {
     fn drop(&mut self) {
         unsafe {
-            ReleaseDC(HWND::default(), self.hdc);
+            ReleaseDC(self.hwnd, self.hdc);
         }
     }
 }
@@ -43,12 +78,6 @@ impl From<&Hdc> for HDC {
     }
 }
 
-impl From<Hdc> for HDC {
-    fn from(item: Hdc) -> Self {
-        item.hdc
-    }
-}
-
 #[allow(dead_code)]
 #[derive(Debug)]
 pub(crate) struct Rect {
@@ -62,6 +91,27 @@ pub(crate) struct Rect {
 }
 
 impl Rect {
+    // Orders the edges so left <= right and top <= bottom, right-to-left
+    // windows can report them swapped. Fails if nothing is left to capture.
+    pub(crate) fn from_rect(rect: RECT) -> Result<Rect, Error> {
+        let (left, right) = (rect.left.min(rect.right), rect.left.max(rect.right));
+        let (top, bottom) = (rect.top.min(rect.bottom), rect.top.max(rect.bottom));
+        match right > left && bottom > top {
+            true => Ok(Rect {
+                left,
+                top,
+                right,
+                bottom,
+                width: right - left,
+                height: bottom - top,
+            }),
+            false => Err(Error::new(
+                ERROR_INCORRECT_SIZE.to_hresult(),
+                "window rect has zero width or height".into(),
+            )),
+        }
+    }
+
     pub(crate) fn get_window_rect<P0>(hwnd: P0) -> Result<Rect, Error>
     where
         P0: Into<HWND>,
@@ -69,14 +119,7 @@ impl Rect {
         let mut rect = RECT::default();
         unsafe {
             match GetWindowRect(hwnd.into(), &mut rect).as_bool() {
-                true => Ok(Rect {
-                    left: rect.left,
-                    top: rect.top,
-                    right: rect.right,
-                    bottom: rect.bottom,
-                    width: rect.right - rect.left,
-                    height: rect.bottom - rect.top,
-                }),
+                true => Rect::from_rect(rect),
                 false => Err(Error::from_win32()),
             }
         }
@@ -88,26 +131,31 @@ impl Rect {
         let mut rect = RECT::default();
         unsafe {
             match GetClientRect(hwnd.into(), &mut rect).as_bool() {
-                true => Ok(Rect {
-                    left: rect.left,
-                    top: rect.top,
-                    right: rect.right,
-                    bottom: rect.bottom,
-                    width: rect.right - rect.left,
-                    height: rect.bottom - rect.top,
-                }),
+                true => Rect::from_rect(rect),
                 false => Err(Error::from_win32()),
             }
         }
     }
 }
 
-pub(crate) struct CreatedHdc {
+impl From<Rect> for WindowRect {
+    fn from(rect: Rect) -> Self {
+        WindowRect {
+            x: rect.left,
+            y: rect.top,
+            width: rect.width,
+            height: rect.height,
+        }
+    }
+}
+
+// memory DC, deleted on drop
+pub struct CreatedHdc {
     pub(crate) hdc: CreatedHDC,
 }
 
 impl CreatedHdc {
-    pub(crate) fn create_compatible_dc<P0>(hdc: P0) -> Result<CreatedHdc, Error>
+    pub fn create_compatible_dc<P0>(hdc: P0) -> Result<CreatedHdc, Error>
     where
         P0: IntoParam<HDC>,
     {
@@ -118,16 +166,22 @@ impl CreatedHdc {
             }
         }
     }
-}
 
-impl From<&CreatedHdc> for HDC {
-    fn from(item: &CreatedHdc) -> Self {
-        HDC(item.hdc.0)
+    pub fn handle(&self) -> CreatedHDC {
+        self.hdc
+    }
+
+    // Gives up ownership of the DC without deleting it, the caller has to
+    // DeleteDC it
+    pub fn into_raw(self) -> CreatedHDC {
+        let hdc = self.hdc;
+        mem::forget(self);
+        hdc
     }
 }
 
-impl From<CreatedHdc> for HDC {
-    fn from(item: CreatedHdc) -> Self {
+impl From<&CreatedHdc> for HDC {
+    fn from(item: &CreatedHdc) -> Self {
         HDC(item.hdc.0)
     }
 }
@@ -140,12 +194,13 @@ impl Drop for CreatedHdc {
     }
 }
 
-pub(crate) struct Hbitmap {
+// bitmap, deleted on drop
+pub struct Hbitmap {
     pub(crate) hbitmap: HBITMAP,
 }
 
 impl Hbitmap {
-    pub(crate) fn create_compatible_bitmap<P0>(hdc: P0, w: i32, h: i32) -> Result<Hbitmap, Error>
+    pub fn create_compatible_bitmap<P0>(hdc: P0, w: i32, h: i32) -> Result<Hbitmap, Error>
     where
         P0: IntoParam<HDC>,
     {
@@ -156,6 +211,18 @@ impl Hbitmap {
             }
         }
     }
+
+    pub fn handle(&self) -> HBITMAP {
+        self.hbitmap
+    }
+
+    // Gives up ownership of the bitmap without deleting it, the caller has
+    // to DeleteObject it
+    pub fn into_raw(self) -> HBITMAP {
+        let hbitmap = self.hbitmap;
+        mem::forget(self);
+        hbitmap
+    }
 }
 
 impl Drop for Hbitmap {
@@ -166,8 +233,42 @@ impl Drop for Hbitmap {
     }
 }
 
-impl From<Hbitmap> for HBITMAP {
-    fn from(item: Hbitmap) -> Self {
-        item.hbitmap
+// Selects a bitmap into a memory DC and restores the previous object on drop.
+// A bitmap can't be deleted or read with GetDIBits while it's selected, so
+// the guard borrows both the DC and the Hbitmap it selected and has to be
+// dropped before either of them.
+pub struct SelectedObject<'a> {
+    hdc: HDC,
+    previous: HGDIOBJ,
+    _borrow: PhantomData<(&'a CreatedHdc, &'a Hbitmap)>,
+}
+
+impl<'a> SelectedObject<'a> {
+    pub fn select(hdc: &'a CreatedHdc, hbitmap: &'a Hbitmap) -> Result<SelectedObject<'a>, Error> {
+        unsafe { SelectedObject::select_raw(HDC::from(hdc), hbitmap.hbitmap) }
+    }
+
+    // Same as select without the borrows, for owners that keep the guard next
+    // to the DC and bitmap. The caller has to drop the guard before either.
+    pub(crate) unsafe fn select_raw(
+        hdc: HDC,
+        hbitmap: HBITMAP,
+    ) -> Result<SelectedObject<'a>, Error> {
+        match SelectObject(hdc, hbitmap) {
+            e if e.is_invalid() => Err(Error::from_win32()),
+            previous => Ok(SelectedObject {
+                hdc,
+                previous,
+                _borrow: PhantomData,
+            }),
+        }
+    }
+}
+
+impl Drop for SelectedObject<'_> {
+    fn drop(&mut self) {
+        unsafe {
+            SelectObject(self.hdc, self.previous);
+        }
     }
 }